@@ -0,0 +1,486 @@
+use std::{collections::HashMap, time};
+
+use tempfile::NamedTempFile;
+
+use crate::{
+    error::{Error, ErrorKind},
+    input::{FFMpegInput, FFMpegMultipleInput, StreamType},
+    output::FFmpegOutput,
+    utils::{escape_filter_value, parse_duration, parse_frame_rate, parse_json_fields},
+    FFMpeg,
+};
+
+pub(crate) const DEFAULT_TRUE_PEAK: f64 = -1.5;
+pub(crate) const DEFAULT_LRA: f64 = 11.0;
+const DEFAULT_TARGET: f64 = -24.0;
+
+/// stream of an input file, as reported by ffmpeg
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StreamInfo {
+    /// index of stream in input file, e.g. 1 for `0:1`
+    pub index: usize,
+    pub stream_type: StreamType,
+    /// codec name, e.g. `h264` or `aac`
+    pub codec: String,
+    pub language: Option<String>,
+    pub title: Option<String>,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub frame_rate: Option<f64>,
+    /// exact base frame rate (`r_frame_rate`) as numerator and denominator
+    pub r_frame_rate: Option<(u32, u32)>,
+    pub sample_rate: Option<u32>,
+    /// channel layout, e.g. `stereo` or `5.1(side)`
+    pub channel_layout: Option<String>,
+    pub default: bool,
+    pub forced: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MediaInfo {
+    pub duration: Option<time::Duration>,
+    pub streams: Vec<StreamInfo>,
+}
+
+/// probe duration and streams of input file, as reported by `ffmpeg -i`
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::{analysis, StreamType};
+/// # use ffmpeg_cli_utils::FFMpeg;
+/// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+/// let info = analysis::probe("./sample.mp4").unwrap();
+/// let audio_streams = info
+///     .streams
+///     .iter()
+///     .filter(|stream| stream.stream_type == StreamType::Audio)
+///     .count();
+/// ```
+pub fn probe(file: &str) -> Result<MediaInfo, Error> {
+    // ffmpeg exits with failure as no output is given, but input info is still printed
+    let output = std::process::Command::new(FFMpeg::get_ffmpeg_bin())
+        .args(["-hide_banner", "-i", file])
+        .stdin(std::process::Stdio::null())
+        .output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.contains("Input #0") {
+        return Err(Error {
+            kind: ErrorKind::Ffmpeg,
+            msg: stderr.into_owned(),
+        });
+    }
+    Ok(MediaInfo {
+        duration: parse_duration(&stderr),
+        streams: parse_streams(&stderr),
+    })
+}
+
+/// duration of input file, as reported by ffmpeg
+pub fn duration(file: &str) -> Result<time::Duration, Error> {
+    probe(file)?.duration.ok_or(Error {
+        kind: ErrorKind::UnexpectedOutput,
+        msg: format!("failed to get duration of {file}"),
+    })
+}
+
+/// e.g.
+/// "  Stream #0:1[0x2](eng): Audio: aac (LC) (mp4a / 0x6134706D), 48000 Hz, stereo, fltp, 125 kb/s (default)"
+/// "    Metadata:"
+/// "      title           : Commentary"
+fn parse_streams(stderr: &str) -> Vec<StreamInfo> {
+    let mut streams: Vec<StreamInfo> = vec![];
+    for line in stderr.lines() {
+        let line = line.trim();
+        if let Some(stream) = line.strip_prefix("Stream #").and_then(parse_stream) {
+            streams.push(stream);
+        } else if line.starts_with("Input #") && !streams.is_empty() {
+            // only the first input is probed
+            break;
+        } else if let (Some(stream), Some((key, value))) =
+            (streams.last_mut(), line.split_once(':'))
+        {
+            if key.trim() == "title" {
+                stream.title = Some(value.trim().to_owned());
+            }
+        }
+    }
+    streams
+}
+
+fn parse_stream(line: &str) -> Option<StreamInfo> {
+    let (id, rest) = line.split_once(": ")?;
+    let (_, id) = id.split_once(':')?;
+    let index = id
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect::<String>()
+        .parse::<usize>()
+        .ok()?;
+    let language = id
+        .split_once('(')
+        .and_then(|(_, language)| language.split_once(')'))
+        .map(|(language, _)| language.to_owned())
+        .filter(|language| language != "und");
+
+    let (stream_type, details) = rest.split_once(": ").unwrap_or((rest, ""));
+    let stream_type = match stream_type {
+        "Video" => StreamType::Video,
+        "Audio" => StreamType::Audio,
+        "Subtitle" => StreamType::Subtitle,
+        "Data" => StreamType::Data,
+        "Attachment" => StreamType::Attachment,
+        _ => return None,
+    };
+    let codec = details
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .next()
+        .unwrap_or_default()
+        .to_owned();
+
+    let mut stream = StreamInfo {
+        index,
+        stream_type,
+        codec,
+        language,
+        default: details.contains("(default)"),
+        forced: details.contains("(forced)"),
+        ..Default::default()
+    };
+    let columns: Vec<&str> = details.split(", ").map(|column| column.trim()).collect();
+    for (i, column) in columns.iter().enumerate() {
+        if let Some(rate) = column.strip_suffix(" Hz") {
+            stream.sample_rate = rate.parse().ok();
+            stream.channel_layout = columns.get(i + 1).map(|layout| layout.to_string());
+        } else if let Some(fps) = column.strip_suffix(" fps") {
+            stream.frame_rate = fps.parse().ok();
+        } else if let Some(tbr) = column.strip_suffix(" tbr") {
+            stream.r_frame_rate = parse_frame_rate(tbr);
+        } else if stream.width.is_none() {
+            let size = column.split_whitespace().next().unwrap_or_default();
+            if let Some((w, h)) = size.split_once('x') {
+                if let (Ok(w), Ok(h)) = (w.parse(), h.parse()) {
+                    stream.width = Some(w);
+                    stream.height = Some(h);
+                }
+            }
+        }
+    }
+    Some(stream)
+}
+
+/// EBU R128 loudness of an input, as reported by `loudnorm`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Loudness {
+    /// integrated loudness in LUFS
+    pub integrated: f64,
+    /// loudness range in LU
+    pub lra: f64,
+    /// true peak in dBTP
+    pub true_peak: f64,
+    /// gating threshold in LUFS
+    pub threshold: f64,
+    /// offset gain loudnorm suggests for a second pass
+    pub target_offset: f64,
+}
+
+/// measure EBU R128 loudness of input file
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::analysis;
+/// # use ffmpeg_cli_utils::FFMpeg;
+/// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+/// let loudness = analysis::loudness("./sample.mp4").unwrap();
+/// println!("{} LUFS", loudness.integrated);
+/// ```
+pub fn loudness(file: &str) -> Result<Loudness, Error> {
+    measure_loudness(FFMpeg::input(file).output(), DEFAULT_TARGET)
+}
+
+/// run `output` through `loudnorm` into the null muxer, `target` only affects
+/// the reported `target_offset`
+pub(crate) fn measure_loudness(output: FFmpegOutput, target: f64) -> Result<Loudness, Error> {
+    let result = loudness_pass(output, target).run("-")?;
+    parse_loudness(&result.stderr)
+}
+
+/// async version of `measure_loudness`
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-process"))]
+pub(crate) async fn async_measure_loudness(
+    output: FFmpegOutput,
+    target: f64,
+) -> Result<Loudness, Error> {
+    let result = loudness_pass(output, target).async_run("-").await?;
+    parse_loudness(&result.stderr)
+}
+
+fn loudness_pass(output: FFmpegOutput, target: f64) -> FFmpegOutput {
+    output
+        .audio_filter(&format!(
+            "loudnorm=I={target}:TP={DEFAULT_TRUE_PEAK}:LRA={DEFAULT_LRA}:print_format=json"
+        ))
+        .args(vec!["-vn", "-sn", "-dn"])
+        .format("null")
+        .verbose()
+}
+
+fn parse_loudness(stderr: &str) -> Result<Loudness, Error> {
+    let fields = parse_json_fields(stderr);
+    let field = |name: &str| -> Result<f64, Error> {
+        let value = fields
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value)
+            .ok_or(Error {
+                kind: ErrorKind::UnexpectedOutput,
+                msg: format!("loudnorm did not report {name}"),
+            })?;
+        value.parse::<f64>().map_err(|_| Error {
+            kind: ErrorKind::UnexpectedOutput,
+            msg: format!("invalid {name} reported by loudnorm: {value}"),
+        })
+    };
+    Ok(Loudness {
+        integrated: field("input_i")?,
+        lra: field("input_lra")?,
+        true_peak: field("input_tp")?,
+        threshold: field("input_thresh")?,
+        target_offset: field("target_offset")?,
+    })
+}
+
+/// crop rectangle, in pixels of the source video
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Crop {
+    pub w: u32,
+    pub h: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl Crop {
+    pub(crate) fn to_filter(self) -> String {
+        format!("crop={}:{}:{}:{}", self.w, self.h, self.x, self.y)
+    }
+}
+
+/// detect black borders of input video by running `cropdetect` at each of
+/// `sample_points`, the most common crop rectangle is returned
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::{analysis, FFMpeg};
+/// use std::time;
+/// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+/// let points: Vec<_> = [10, 20, 30].iter().map(|s| time::Duration::from_secs(*s)).collect();
+/// let crop = analysis::detect_crop("./sample.mp4", &points).unwrap();
+/// FFMpeg::input("./sample.mp4")
+///     .output()
+///     .crop(crop)
+///     .resize(-2, 480)
+///     .save("./output/cropped.mp4")
+///     .unwrap();
+/// ```
+pub fn detect_crop(file: &str, sample_points: &[time::Duration]) -> Result<Crop, Error> {
+    let mut counts: HashMap<Crop, usize> = HashMap::new();
+    for point in sample_points {
+        let mut output = FFMpeg::input(file)
+            .start_time(point)
+            .output()
+            .video_filter("cropdetect")
+            .args(vec!["-frames:v", "25", "-an", "-sn", "-dn"])
+            .format("null")
+            .verbose();
+        let result = output.run("-")?;
+        if let Some(crop) = result.stderr.lines().rev().find_map(parse_crop) {
+            *counts.entry(crop).or_default() += 1;
+        }
+    }
+    counts
+        .into_iter()
+        .max_by_key(|(_, count)| *count)
+        .map(|(crop, _)| crop)
+        .ok_or(Error {
+            kind: ErrorKind::UnexpectedOutput,
+            msg: "cropdetect did not report any crop area".to_owned(),
+        })
+}
+
+fn parse_crop(line: &str) -> Option<Crop> {
+    let (_, crop) = line.rsplit_once("crop=")?;
+    let mut values = crop.trim().split(':').map(|v| v.parse::<u32>().ok());
+    Some(Crop {
+        w: values.next()??,
+        h: values.next()??,
+        x: values.next()??,
+        y: values.next()??,
+    })
+}
+
+/// objective video quality metric
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Metric {
+    Psnr,
+    Ssim,
+    /// only computed when ffmpeg is built with `libvmaf`
+    Vmaf,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MetricScores {
+    /// aggregate score over all frames as reported by ffmpeg
+    pub average: f64,
+    /// score of each frame, in presentation order
+    pub frames: Vec<f64>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QualityReport {
+    /// average PSNR over Y, U and V planes in dB
+    pub psnr: Option<MetricScores>,
+    /// SSIM over all planes, between 0 and 1
+    pub ssim: Option<MetricScores>,
+    /// VMAF score, between 0 and 100
+    pub vmaf: Option<MetricScores>,
+}
+
+/// compare `distorted` video against `reference` video with given metrics,
+/// distorted video is scaled to the size of reference video before comparison
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::analysis::{self, Metric};
+/// # use ffmpeg_cli_utils::FFMpeg;
+/// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+/// let report = analysis::compare(
+///     "./sample.mp4",
+///     "./output/output_320p.mp4",
+///     &[Metric::Psnr, Metric::Ssim, Metric::Vmaf],
+/// )
+/// .unwrap();
+/// println!("psnr: {:?}", report.psnr.map(|psnr| psnr.average));
+/// ```
+pub fn compare(reference: &str, distorted: &str, metrics: &[Metric]) -> Result<QualityReport, Error> {
+    // each metric once, a repeated filter would share log and stats files
+    let mut unique: Vec<Metric> = vec![];
+    for metric in metrics {
+        if !unique.contains(metric) {
+            unique.push(*metric);
+        }
+    }
+    let mut metrics = unique;
+    if metrics.contains(&Metric::Vmaf) && !FFMpeg::has_filter("libvmaf") {
+        metrics.retain(|metric| *metric != Metric::Vmaf);
+    }
+    if metrics.is_empty() {
+        return Ok(QualityReport::default());
+    }
+
+    let mut stats_files = vec![];
+    let mut graph = vec!["[1:v][0:v]scale2ref=flags=bicubic[dist][ref]".to_owned()];
+    let n = metrics.len();
+    graph.push(format!(
+        "[dist]split={n}{}",
+        (0..n).map(|i| format!("[dist{i}]")).collect::<String>()
+    ));
+    graph.push(format!(
+        "[ref]split={n}{}",
+        (0..n).map(|i| format!("[ref{i}]")).collect::<String>()
+    ));
+    for (i, metric) in metrics.iter().enumerate() {
+        let stats_file = NamedTempFile::new()?;
+        let path = escape_filter_value(&stats_file.path().to_string_lossy());
+        let filter = match metric {
+            Metric::Psnr => format!("psnr=stats_file={path}"),
+            Metric::Ssim => format!("ssim=stats_file={path}"),
+            Metric::Vmaf => format!("libvmaf=log_fmt=csv:log_path={path}"),
+        };
+        graph.push(format!("[dist{i}][ref{i}]{filter}"));
+        stats_files.push(stats_file);
+    }
+
+    let reference = FFMpegInput::input(reference);
+    let distorted = FFMpegInput::input(distorted);
+    let mut output = FFMpegMultipleInput::merge(&reference, &distorted)
+        .output()
+        .filter_complex(&graph.join(";"))
+        .args(vec!["-an", "-sn", "-dn"])
+        .format("null")
+        .verbose();
+    let result = output.run("-")?;
+
+    let mut report = QualityReport::default();
+    for (metric, stats_file) in metrics.iter().zip(stats_files.iter()) {
+        let stats = std::fs::read_to_string(stats_file.path())?;
+        let scores = match metric {
+            Metric::Psnr => {
+                let frames = parse_stats_field(&stats, "psnr_avg:");
+                let average = parse_summary(&result.stderr, "PSNR ", "average:");
+                MetricScores::new(frames, average)
+            }
+            Metric::Ssim => {
+                let frames = parse_stats_field(&stats, "All:");
+                let average = parse_summary(&result.stderr, "SSIM ", "All:");
+                MetricScores::new(frames, average)
+            }
+            Metric::Vmaf => {
+                let frames = parse_csv_column(&stats, "vmaf");
+                let average = parse_summary(&result.stderr, "VMAF score", "score:");
+                MetricScores::new(frames, average)
+            }
+        };
+        match metric {
+            Metric::Psnr => report.psnr = Some(scores),
+            Metric::Ssim => report.ssim = Some(scores),
+            Metric::Vmaf => report.vmaf = Some(scores),
+        }
+    }
+    Ok(report)
+}
+
+impl MetricScores {
+    /// falls back to mean of frame scores when ffmpeg did not print a summary
+    fn new(frames: Vec<f64>, average: Option<f64>) -> Self {
+        let average = average.unwrap_or_else(|| {
+            if frames.is_empty() {
+                0.0
+            } else {
+                frames.iter().sum::<f64>() / frames.len() as f64
+            }
+        });
+        Self { average, frames }
+    }
+}
+
+/// e.g. "n:1 mse_avg:2.97 ... psnr_avg:43.40 ..." => 43.40
+fn parse_stats_field(stats: &str, key: &str) -> Vec<f64> {
+    stats
+        .lines()
+        .filter_map(|line| {
+            line.split_whitespace()
+                .find_map(|column| column.strip_prefix(key))
+                .and_then(|value| value.parse::<f64>().ok())
+        })
+        .collect()
+}
+
+fn parse_csv_column(csv: &str, name: &str) -> Vec<f64> {
+    let mut lines = csv.lines();
+    let column = match lines
+        .next()
+        .and_then(|header| header.split(',').position(|column| column.trim() == name))
+    {
+        Some(column) => column,
+        None => return vec![],
+    };
+    lines
+        .filter_map(|line| line.split(',').nth(column)?.trim().parse::<f64>().ok())
+        .collect()
+}
+
+/// find value following `key` on the last stderr line containing `marker`
+fn parse_summary(stderr: &str, marker: &str, key: &str) -> Option<f64> {
+    let line = stderr.lines().rev().find(|line| line.contains(marker))?;
+    let (_, value) = line.split_once(key)?;
+    value.split_whitespace().next()?.parse::<f64>().ok()
+}
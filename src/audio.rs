@@ -0,0 +1,213 @@
+use std::path::Path;
+
+use crate::{
+    analysis,
+    error::{Error, ErrorKind},
+    input::{FFMpegInput, FFMpegMultipleInput},
+    output::FFmpegOutput,
+    FFMpeg,
+};
+
+/// mix audio of inputs into one track, each with its weight, `inputs` must
+/// not be empty. mixing is done by `filter_complex`, so `audio_filter` can
+/// not be added to the output
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::audio;
+/// # use ffmpeg_cli_utils::FFMpeg;
+/// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+/// audio::mix(&[("./narration.wav", 1.0), ("./music.mp3", 0.3)])
+///     .unwrap()
+///     .save("./output/mixed.m4a")
+///     .unwrap();
+/// ```
+pub fn mix(inputs: &[(&str, f32)]) -> Result<FFmpegOutput, Error> {
+    if inputs.is_empty() {
+        return Err(Error {
+            kind: ErrorKind::InvalidOption,
+            msg: "at least one input is required to mix".to_owned(),
+        });
+    }
+    let mut multiple_input = FFMpegMultipleInput::new(&FFMpegInput::input(inputs[0].0));
+    let rest: Vec<FFMpegInput> = inputs[1..]
+        .iter()
+        .map(|(file, _)| FFMpegInput::input(file))
+        .collect();
+    multiple_input.append(rest.iter().collect());
+
+    let labels: String = (0..inputs.len()).map(|i| format!("[{i}:a]")).collect();
+    let weights: Vec<String> = inputs
+        .iter()
+        .map(|(_, weight)| weight.to_string())
+        .collect();
+    let graph = format!(
+        "{labels}amix=inputs={}:duration=longest:weights={}:normalize=0[mixed]",
+        inputs.len(),
+        weights.join(" ")
+    );
+    Ok(multiple_input
+        .output()
+        .filter_complex(&graph)
+        .args(vec!["-map", "[mixed]"]))
+}
+
+/// sidechain compression settings of `duck`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ducking {
+    /// voice level above which music is compressed, from 0 to 1
+    pub threshold: f32,
+    /// compression ratio applied to music while voice is present
+    pub ratio: f32,
+    /// milliseconds before music is fully ducked
+    pub attack: f32,
+    /// milliseconds before music recovers
+    pub release: f32,
+    /// volume of music before ducking
+    pub music_volume: f32,
+}
+
+impl Default for Ducking {
+    fn default() -> Self {
+        Self {
+            threshold: 0.05,
+            ratio: 8.0,
+            attack: 20.0,
+            release: 400.0,
+            music_volume: 0.8,
+        }
+    }
+}
+
+/// mix `music` under `voice`, music is lowered by a sidechain compressor
+/// whenever voice is present, output lasts as long as voice. as with `mix`,
+/// `audio_filter` can not be added to the output
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::audio::{self, Ducking};
+/// # use ffmpeg_cli_utils::FFMpeg;
+/// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+/// audio::duck("./narration.wav", "./music.mp3", &Ducking::default())
+///     .save("./output/voiceover.m4a")
+///     .unwrap();
+/// ```
+pub fn duck(voice: &str, music: &str, ducking: &Ducking) -> FFmpegOutput {
+    let graph = [
+        "[0:a]asplit=2[voice][sidechain]".to_owned(),
+        format!("[1:a]volume={}[music]", ducking.music_volume),
+        format!(
+            "[music][sidechain]sidechaincompress=threshold={}:ratio={}:attack={}:release={}[ducked]",
+            ducking.threshold, ducking.ratio, ducking.attack, ducking.release
+        ),
+        "[voice][ducked]amix=inputs=2:duration=first:normalize=0[mixed]".to_owned(),
+    ];
+    FFMpegMultipleInput::merge(&FFMpegInput::input(voice), &FFMpegInput::input(music))
+        .output()
+        .filter_complex(&graph.join(";"))
+        .args(vec!["-map", "[mixed]"])
+}
+
+/// downmix 5.1 audio to stereo with ITU-R BS.775 coefficients, center and
+/// surround channels are mixed in at -3 dB and LFE is dropped
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::audio;
+/// # use ffmpeg_cli_utils::FFMpeg;
+/// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+/// audio::downmix_to_stereo("./movie_5.1.mkv")
+///     .unwrap()
+///     .save("./output/stereo.mkv")
+///     .unwrap();
+/// ```
+pub fn downmix_to_stereo(file: &str) -> Result<FFmpegOutput, Error> {
+    let layout = channel_layout(file)?;
+    let (left, right) = if layout.contains("side") {
+        ("SL", "SR")
+    } else {
+        ("BL", "BR")
+    };
+    // "<" renormalizes gains so the sum of each output channel does not clip
+    let pan = format!("pan=stereo|FL<FL+0.707*FC+0.707*{left}|FR<FR+0.707*FC+0.707*{right}");
+    Ok(FFMpeg::input(file).output().audio_filter(&pan))
+}
+
+/// upmix stereo audio to 5.1, center is the sum of both sides at -6 dB,
+/// surrounds repeat front channels at -3 dB and LFE is left silent
+pub fn upmix_to_5_1(file: &str) -> FFmpegOutput {
+    FFMpeg::input(file)
+        .output()
+        .audio_filter("pan=5.1|FL=FL|FR=FR|FC=0.5*FL+0.5*FR|LFE=0*FL|BL=0.707*FL|BR=0.707*FR")
+}
+
+/// extract one channel of input audio as mono, `channel` is a channel name
+/// like `FL` or `LFE`, or `c0`, `c1`... for channel index
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::audio;
+/// # use ffmpeg_cli_utils::FFMpeg;
+/// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+/// audio::extract_channel("./sample.mp4", "FL")
+///     .save("./output/left.wav")
+///     .unwrap();
+/// ```
+pub fn extract_channel(file: &str, channel: &str) -> FFmpegOutput {
+    FFMpeg::input(file)
+        .only_audio()
+        .take_stream(0)
+        .output()
+        .audio_filter(&format!("pan=mono|c0={channel}"))
+}
+
+/// split every channel of input audio into mono files named by pattern, e.g.
+/// `channel_%d.wav`, returns paths of saved files
+pub fn split_channels(file: &str, pattern: &str) -> Result<Vec<String>, Error> {
+    let layout = channel_layout(file)?;
+    let channels = channel_count(&layout).ok_or(Error {
+        kind: ErrorKind::UnexpectedOutput,
+        msg: format!("unknown channel layout {layout} of {file}"),
+    })?;
+    let mut files = vec![];
+    for i in 0..channels {
+        let output_file = crate::utils::expand_sequence_pattern(pattern, i);
+        if Path::new(&output_file) == Path::new(pattern) && channels > 1 {
+            return Err(Error {
+                kind: ErrorKind::InvalidOption,
+                msg: format!("file name pattern {pattern} has no sequence number"),
+            });
+        }
+        extract_channel(file, &format!("c{i}")).save(&output_file)?;
+        files.push(output_file);
+    }
+    Ok(files)
+}
+
+fn channel_layout(file: &str) -> Result<String, Error> {
+    analysis::probe(file)?
+        .streams
+        .into_iter()
+        .find_map(|stream| stream.channel_layout)
+        .ok_or(Error {
+            kind: ErrorKind::StreamSelection,
+            msg: format!("no audio stream found in {file}"),
+        })
+}
+
+fn channel_count(layout: &str) -> Option<usize> {
+    let layout = layout.split('(').next()?;
+    let count = match layout {
+        "mono" => 1,
+        "stereo" | "downmix" => 2,
+        "2.1" | "3.0" => 3,
+        "3.1" | "4.0" | "quad" => 4,
+        "4.1" | "5.0" => 5,
+        "5.1" | "6.0" | "hexagonal" => 6,
+        "6.1" | "7.0" => 7,
+        "7.1" | "octagonal" => 8,
+        // e.g. "10 channels"
+        other => other.strip_suffix(" channels")?.parse().ok()?,
+    };
+    Some(count)
+}
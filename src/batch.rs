@@ -0,0 +1,362 @@
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread, time,
+};
+
+use crate::{
+    analysis,
+    error::{Error, ErrorKind},
+    input::StreamType,
+    output::FFmpegOutput,
+    utils::glob_match,
+    FFMpegInput,
+};
+
+type Configure = Arc<dyn Fn(FFmpegOutput) -> FFmpegOutput + Send + Sync>;
+
+/// transcode every file of `input_dir` into `output_dir`, keeping layout of
+/// sub directories
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::batch;
+/// # use ffmpeg_cli_utils::FFMpeg;
+/// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+/// let report = batch::transcode_dir("./videos", "./output/videos")
+///     .include("*.mp4")
+///     .include("*.mov")
+///     .exclude("drafts/**")
+///     .template("{stem}_{height}p.{ext}")
+///     .height(720)
+///     .extension("mp4")
+///     .parallel(4)
+///     .output(|output| output.set_bitrate(2_000_000))
+///     .run()
+///     .unwrap();
+/// report.save("./output/videos/report.tsv").unwrap();
+/// println!("{report}");
+/// ```
+pub fn transcode_dir(input_dir: &str, output_dir: &str) -> Batch {
+    Batch {
+        input_dir: PathBuf::from(input_dir),
+        output_dir: PathBuf::from(output_dir),
+        include: vec![],
+        exclude: vec![],
+        template: "{stem}.{ext}".to_owned(),
+        height: None,
+        extension: None,
+        parallel: 1,
+        force: false,
+        configure: None,
+    }
+}
+
+#[derive(Clone)]
+pub struct Batch {
+    input_dir: PathBuf,
+    output_dir: PathBuf,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    template: String,
+    height: Option<i32>,
+    extension: Option<String>,
+    parallel: usize,
+    force: bool,
+    configure: Option<Configure>,
+}
+
+impl Batch {
+    /// only transcode files matching `glob`, patterns without `/` match file
+    /// name, others match path relative to input dir. `*` and `?` stay in a
+    /// path segment, `**` crosses them. all files are included by default
+    pub fn include(mut self, glob: &str) -> Self {
+        self.include.push(glob.to_owned());
+        self
+    }
+
+    /// skip files matching `glob`, same syntax as `include`
+    pub fn exclude(mut self, glob: &str) -> Self {
+        self.exclude.push(glob.to_owned());
+        self
+    }
+
+    /// path of output relative to output dir, `{stem}`, `{name}`, `{ext}` and
+    /// `{height}` are replaced. `{ext}` is the input extension unless
+    /// `extension` is set, `{height}` is probed from input unless `height` is
+    /// set. default is `{stem}.{ext}`
+    pub fn template(mut self, template: &str) -> Self {
+        self.template = template.to_owned();
+        self
+    }
+
+    /// resize outputs to `height`, keeping aspect ratio
+    pub fn height(mut self, height: i32) -> Self {
+        self.height = Some(height);
+        self
+    }
+
+    /// extension of outputs, e.g. `mp4`
+    pub fn extension(mut self, extension: &str) -> Self {
+        self.extension = Some(extension.trim_start_matches('.').to_owned());
+        self
+    }
+
+    /// number of files transcoded at once, default is 1
+    pub fn parallel(mut self, parallel: usize) -> Self {
+        self.parallel = parallel.max(1);
+        self
+    }
+
+    /// transcode even if output is newer than input
+    pub fn force(mut self) -> Self {
+        self.force = true;
+        self
+    }
+
+    /// customize output of every file, e.g. bitrate or codecs
+    pub fn output(
+        mut self,
+        f: impl Fn(FFmpegOutput) -> FFmpegOutput + Send + Sync + 'static,
+    ) -> Self {
+        self.configure = Some(Arc::new(f));
+        self
+    }
+
+    /// transcode all matching files, failures of single files are recorded
+    /// in report instead of stopping the batch
+    pub fn run(&self) -> Result<BatchReport, Error> {
+        let mut inputs = vec![];
+        self.walk(&self.input_dir, &mut inputs)?;
+        let pending = Mutex::new(inputs.into_iter());
+        let entries = Mutex::new(vec![]);
+        thread::scope(|scope| {
+            for _ in 0..self.parallel {
+                scope.spawn(|| loop {
+                    let input = pending.lock().unwrap().next();
+                    let Some(input) = input else {
+                        break;
+                    };
+                    let entry = self.transcode(input);
+                    entries.lock().unwrap().push(entry);
+                });
+            }
+        });
+        let mut entries = entries.into_inner().unwrap();
+        entries.sort_by(|a, b| a.input.cmp(&b.input));
+        Ok(BatchReport { entries })
+    }
+
+    fn walk(&self, dir: &Path, inputs: &mut Vec<PathBuf>) -> Result<(), Error> {
+        let mut dir_entries = fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        dir_entries.sort();
+        for path in dir_entries {
+            // outputs may be written inside input dir, never pick them up again
+            if path.starts_with(&self.output_dir) {
+                continue;
+            }
+            if path.is_dir() {
+                self.walk(&path, inputs)?;
+            } else if self.matches(&path) {
+                inputs.push(path);
+            }
+        }
+        Ok(())
+    }
+
+    fn matches(&self, path: &Path) -> bool {
+        let relative = self.relative(path);
+        let name = path.file_name().unwrap_or_default().to_string_lossy();
+        let matched = |glob: &String| {
+            if glob.contains('/') {
+                glob_match(glob, &relative)
+            } else {
+                glob_match(glob, &name)
+            }
+        };
+        (self.include.is_empty() || self.include.iter().any(matched))
+            && !self.exclude.iter().any(matched)
+    }
+
+    /// path relative to input dir with `/` separators
+    fn relative(&self, path: &Path) -> String {
+        let relative = path.strip_prefix(&self.input_dir).unwrap_or(path);
+        relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
+    fn output_path(&self, input: &Path) -> Result<PathBuf, Error> {
+        let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+        let name = input.file_name().unwrap_or_default().to_string_lossy();
+        let ext = match self.extension {
+            Some(ref ext) => ext.clone(),
+            None => input
+                .extension()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned(),
+        };
+        let mut file = self
+            .template
+            .replace("{stem}", &stem)
+            .replace("{name}", &name)
+            .replace("{ext}", &ext);
+        if file.contains("{height}") {
+            let height = match self.height {
+                Some(height) => height.to_string(),
+                None => probe_height(input)?.to_string(),
+            };
+            file = file.replace("{height}", &height);
+        }
+        let parent = input
+            .strip_prefix(&self.input_dir)
+            .ok()
+            .and_then(Path::parent)
+            .unwrap_or(Path::new(""));
+        Ok(self.output_dir.join(parent).join(file))
+    }
+
+    fn transcode(&self, input: PathBuf) -> BatchEntry {
+        let start = time::Instant::now();
+        let mut entry = BatchEntry {
+            input,
+            output: PathBuf::new(),
+            status: BatchStatus::Done,
+            elapsed: time::Duration::ZERO,
+        };
+        let result = self.output_path(&entry.input).and_then(|output| {
+            entry.output = output;
+            if !self.force && is_up_to_date(&entry.input, &entry.output) {
+                entry.status = BatchStatus::Skipped;
+                return Ok(());
+            }
+            if let Some(parent) = entry.output.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut output = FFMpegInput::input(&entry.input).output();
+            if let Some(height) = self.height {
+                output = output.resize(-2, height);
+            }
+            if let Some(ref configure) = self.configure {
+                output = configure(output);
+            }
+            output.save(&entry.output.to_string_lossy()).map(|_| ())
+        });
+        if let Err(e) = result {
+            entry.status = BatchStatus::Failed(e);
+        }
+        entry.elapsed = start.elapsed();
+        entry
+    }
+}
+
+fn probe_height(input: &Path) -> Result<u32, Error> {
+    analysis::probe(&input.to_string_lossy())?
+        .streams
+        .iter()
+        .filter(|stream| stream.stream_type == StreamType::Video)
+        .find_map(|stream| stream.height)
+        .ok_or_else(|| Error {
+            kind: ErrorKind::StreamSelection,
+            msg: format!("no video stream to take height from in {}", input.display()),
+        })
+}
+
+/// output is newer than its input, so that transcoding it again changes nothing
+fn is_up_to_date(input: &Path, output: &Path) -> bool {
+    let modified = |path: &Path| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    match (modified(input), modified(output)) {
+        (Some(input), Some(output)) => output >= input,
+        _ => false,
+    }
+}
+
+#[derive(Debug)]
+pub enum BatchStatus {
+    Done,
+    /// output is already up to date
+    Skipped,
+    Failed(Error),
+}
+
+#[derive(Debug)]
+pub struct BatchEntry {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub status: BatchStatus,
+    pub elapsed: time::Duration,
+}
+
+/// result of every file of a batch, in order of input path
+#[derive(Debug)]
+pub struct BatchReport {
+    pub entries: Vec<BatchEntry>,
+}
+
+impl BatchReport {
+    pub fn done(&self) -> usize {
+        self.count(|status| matches!(status, BatchStatus::Done))
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.count(|status| matches!(status, BatchStatus::Skipped))
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count(|status| matches!(status, BatchStatus::Failed(_)))
+    }
+
+    fn count(&self, f: impl Fn(&BatchStatus) -> bool) -> usize {
+        self.entries.iter().filter(|entry| f(&entry.status)).count()
+    }
+
+    /// one tab separated line per file: status, input, output, seconds, error
+    pub fn to_tsv(&self) -> String {
+        let mut tsv = String::from("status\tinput\toutput\tseconds\terror\n");
+        for entry in &self.entries {
+            let (status, error) = match entry.status {
+                BatchStatus::Done => ("done", String::new()),
+                BatchStatus::Skipped => ("skipped", String::new()),
+                BatchStatus::Failed(ref e) => ("failed", e.to_string()),
+            };
+            let error = error.split_whitespace().collect::<Vec<_>>().join(" ");
+            tsv.push_str(&format!(
+                "{status}\t{}\t{}\t{:.2}\t{error}\n",
+                entry.input.display(),
+                entry.output.display(),
+                entry.elapsed.as_secs_f64(),
+            ));
+        }
+        tsv
+    }
+
+    /// write report as `to_tsv` to `file`
+    pub fn save(&self, file: &str) -> Result<(), Error> {
+        fs::write(file, self.to_tsv())?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for BatchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} done, {} skipped, {} failed",
+            self.done(),
+            self.skipped(),
+            self.failed()
+        )?;
+        for entry in &self.entries {
+            if let BatchStatus::Failed(ref e) = entry.status {
+                writeln!(f, "failed {}: {}", entry.input.display(), e.msg.trim())?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,112 @@
+use std::{env, process};
+
+use ffmpeg_cli_utils::{batch, FFMpeg};
+
+const USAGE: &str = "usage: ffmpeg_utils batch <input_dir> <output_dir> [options]
+
+options:
+    --include <glob>      only transcode matching files, repeatable
+    --exclude <glob>      skip matching files, repeatable
+    --template <tpl>      output path template, default {stem}.{ext}
+    --height <px>         resize outputs to height, keeping aspect ratio
+    --ext <ext>           extension of outputs
+    --jobs <n>            files transcoded at once, default 1
+    --report <file>       write a tab separated report of all files
+    --force               transcode even if output is up to date
+    --ffmpeg <path>       ffmpeg binary, default ffmpeg in $PATH";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let code = match args.first().map(String::as_str) {
+        Some("batch") => run_batch(&args[1..]),
+        _ => usage(),
+    };
+    process::exit(code);
+}
+
+fn usage() -> i32 {
+    eprintln!("{USAGE}");
+    2
+}
+
+fn run_batch(args: &[String]) -> i32 {
+    let mut positional = vec![];
+    let mut report = None;
+    let mut options = vec![];
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--force" => options.push((arg.as_str(), "")),
+            "--include" | "--exclude" | "--template" | "--height" | "--ext" | "--jobs"
+            | "--report" | "--ffmpeg" => {
+                let Some(value) = args.next() else {
+                    eprintln!("missing value of {arg}");
+                    return usage();
+                };
+                if arg == "--report" {
+                    report = Some(value.as_str());
+                } else {
+                    options.push((arg.as_str(), value.as_str()));
+                }
+            }
+            _ if arg.starts_with("--") => {
+                eprintln!("unknown option {arg}");
+                return usage();
+            }
+            _ => positional.push(arg.as_str()),
+        }
+    }
+    let [input_dir, output_dir] = positional[..] else {
+        return usage();
+    };
+
+    let mut batch = batch::transcode_dir(input_dir, output_dir);
+    for (option, value) in options {
+        batch = match option {
+            "--include" => batch.include(value),
+            "--exclude" => batch.exclude(value),
+            "--template" => batch.template(value),
+            "--ext" => batch.extension(value),
+            "--force" => batch.force(),
+            "--ffmpeg" => {
+                FFMpeg::set_ffmpeg_bin(value);
+                batch
+            }
+            "--height" => match value.parse::<i32>() {
+                Ok(height) if height > 0 => batch.height(height),
+                _ => {
+                    eprintln!("--height expects a positive number, got {value}");
+                    return usage();
+                }
+            },
+            "--jobs" => {
+                let Ok(jobs) = value.parse::<usize>() else {
+                    eprintln!("--jobs expects a number, got {value}");
+                    return usage();
+                };
+                batch.parallel(jobs)
+            }
+            _ => unreachable!(),
+        };
+    }
+
+    let result = match batch.run() {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("{e}");
+            return 1;
+        }
+    };
+    print!("{result}");
+    if let Some(report) = report {
+        if let Err(e) = result.save(report) {
+            eprintln!("failed to write report: {e}");
+            return 1;
+        }
+    }
+    if result.failed() > 0 {
+        1
+    } else {
+        0
+    }
+}
@@ -0,0 +1,126 @@
+use std::{path::Path, time};
+
+use crate::owned;
+
+/// container for output that is consumed while it is being written, e.g. by
+/// `stream`, `blocking_stream` or `write_to`, where muxer can not seek back
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::{container::StreamingContainer, FFMpeg};
+/// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+/// let container = StreamingContainer::fragmented_mp4()
+///     .fragment_duration(std::time::Duration::from_secs(2));
+/// let content_type = container.content_type();
+/// let reader = FFMpeg::input("./sample.mp4")
+///     .output()
+///     .streaming_container(container)
+///     .blocking_stream()
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StreamingContainer {
+    /// mp4 split in self-contained fragments, a fragment is cut at every
+    /// keyframe or, if set, after `fragment_duration`
+    FragmentedMp4 {
+        fragment_duration: Option<time::Duration>,
+    },
+    MpegTs,
+    /// matroska written as a live stream, without seeking back for cues
+    Matroska,
+    /// webm written as a live stream, without seeking back for cues
+    WebM,
+    /// raw aac in ADTS frames
+    Adts,
+    Ogg,
+}
+
+impl StreamingContainer {
+    pub fn fragmented_mp4() -> Self {
+        StreamingContainer::FragmentedMp4 {
+            fragment_duration: None,
+        }
+    }
+
+    /// max duration of a fragment, only used by `FragmentedMp4`
+    pub fn fragment_duration(self, duration: time::Duration) -> Self {
+        match self {
+            StreamingContainer::FragmentedMp4 { .. } => StreamingContainer::FragmentedMp4 {
+                fragment_duration: Some(duration),
+            },
+            other => other,
+        }
+    }
+
+    /// name of ffmpeg muxer, as passed to `-f`
+    pub fn format(&self) -> &'static str {
+        match self {
+            StreamingContainer::FragmentedMp4 { .. } => "mp4",
+            StreamingContainer::MpegTs => "mpegts",
+            StreamingContainer::Matroska => "matroska",
+            StreamingContainer::WebM => "webm",
+            StreamingContainer::Adts => "adts",
+            StreamingContainer::Ogg => "ogg",
+        }
+    }
+
+    /// value of `Content-Type` header when output is served over http
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            StreamingContainer::FragmentedMp4 { .. } => "video/mp4",
+            StreamingContainer::MpegTs => "video/mp2t",
+            StreamingContainer::Matroska => "video/x-matroska",
+            StreamingContainer::WebM => "video/webm",
+            StreamingContainer::Adts => "audio/aac",
+            StreamingContainer::Ogg => "audio/ogg",
+        }
+    }
+
+    /// `-f` and the muxer options that make the container writable to a pipe
+    pub(crate) fn muxer_args(&self) -> Vec<String> {
+        let mut args = owned!["-f", self.format()];
+        match self {
+            StreamingContainer::FragmentedMp4 { fragment_duration } => {
+                args.append(&mut owned![
+                    "-movflags",
+                    "frag_keyframe+empty_moov+default_base_moof"
+                ]);
+                if let Some(duration) = fragment_duration {
+                    args.append(&mut owned![
+                        "-frag_duration",
+                        &duration.as_micros().to_string()
+                    ]);
+                }
+            }
+            StreamingContainer::Matroska | StreamingContainer::WebM => {
+                args.append(&mut owned!["-live", "1"]);
+            }
+            StreamingContainer::MpegTs | StreamingContainer::Adts | StreamingContainer::Ogg => {}
+        }
+        args
+    }
+}
+
+/// whether output written to `file` in `format` is an mp4 family container,
+/// which gets `faststart` so that playback can begin before download ends
+pub(crate) fn is_mp4(file: &str, format: Option<&str>) -> bool {
+    if let Some(format) = format {
+        return matches!(format, "mp4" | "mov" | "ipod" | "ismv");
+    }
+    let ext = Path::new(file)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    matches!(ext.as_deref(), Some("mp4" | "m4v" | "m4a" | "mov"))
+}
+
+/// whether output written to `file` in `format` is matroska, which can carry
+/// attachments such as fonts of ass subtitles
+pub(crate) fn is_matroska(file: &str, format: Option<&str>) -> bool {
+    if let Some(format) = format {
+        return format == "matroska";
+    }
+    let ext = Path::new(file)
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase());
+    matches!(ext.as_deref(), Some("mkv" | "mka"))
+}
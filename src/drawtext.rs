@@ -0,0 +1,180 @@
+use std::time;
+
+use crate::{tools::Position, utils::escape_filter_value};
+
+#[derive(Clone, Debug)]
+enum TextSource {
+    Text(String),
+    File(String),
+    Timecode { start: String, rate: f64 },
+}
+
+/// typed `drawtext` filter, all values are escaped for filter and filtergraph parsing
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::{drawtext::DrawText, tools::Position, FFMpeg};
+/// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+/// FFMpeg::input("./sample.mp4")
+///     .output()
+///     .draw_text(
+///         DrawText::text("DRAFT: don't share")
+///             .font_size(48)
+///             .font_color("white@0.8")
+///             .position(Position::Center, 0),
+///     )
+///     .draw_text(
+///         DrawText::timecode("00:00:00:00", 24.0)
+///             .background("black@0.5", 8)
+///             .position(Position::BottomLeft, 20),
+///     )
+///     .save("./output/draft.mp4")
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct DrawText {
+    source: TextSource,
+    font_file: Option<String>,
+    font: Option<String>,
+    font_size: u32,
+    font_color: String,
+    background: Option<(String, u32)>,
+    x: String,
+    y: String,
+    enable: Option<(time::Duration, time::Duration)>,
+}
+
+impl DrawText {
+    fn new(source: TextSource) -> Self {
+        Self {
+            source,
+            font_file: None,
+            font: None,
+            font_size: 24,
+            font_color: "white".to_owned(),
+            background: None,
+            x: "0".to_owned(),
+            y: "0".to_owned(),
+            enable: None,
+        }
+    }
+
+    /// draw literal `text`, `%` is not expanded
+    pub fn text(text: &str) -> Self {
+        Self::new(TextSource::Text(text.to_owned()))
+    }
+
+    /// draw content of text file at `path`
+    pub fn text_file(path: &str) -> Self {
+        Self::new(TextSource::File(path.to_owned()))
+    }
+
+    /// burn in SMPTE timecode starting from `start` (`hh:mm:ss:ff`), counting at `rate` fps
+    pub fn timecode(start: &str, rate: f64) -> Self {
+        Self::new(TextSource::Timecode {
+            start: start.to_owned(),
+            rate,
+        })
+    }
+
+    /// font file to use, required when ffmpeg is built without fontconfig
+    pub fn font_file(mut self, path: &str) -> Self {
+        self.font_file = Some(path.to_owned());
+        self
+    }
+
+    /// fontconfig font family, e.g. `Sans`
+    pub fn font(mut self, font: &str) -> Self {
+        self.font = Some(font.to_owned());
+        self
+    }
+
+    pub fn font_size(mut self, size: u32) -> Self {
+        self.font_size = size;
+        self
+    }
+
+    /// ffmpeg color, e.g. `white`, `#ff0000` or `black@0.5`
+    pub fn font_color(mut self, color: &str) -> Self {
+        self.font_color = color.to_owned();
+        self
+    }
+
+    /// draw a box of `color` behind text, extending `border` pixels around it
+    pub fn background(mut self, color: &str, border: u32) -> Self {
+        self.background = Some((color.to_owned(), border));
+        self
+    }
+
+    pub fn position(mut self, position: Position, margin: u32) -> Self {
+        let (x, y) = match position {
+            Position::TopLeft => (format!("{margin}"), format!("{margin}")),
+            Position::TopRight => (format!("w-tw-{margin}"), format!("{margin}")),
+            Position::BottomLeft => (format!("{margin}"), format!("h-th-{margin}")),
+            Position::BottomRight => (format!("w-tw-{margin}"), format!("h-th-{margin}")),
+            Position::Center => ("(w-tw)/2".to_owned(), "(h-th)/2".to_owned()),
+            Position::Custom(x, y) => (x, y),
+        };
+        self.x = x;
+        self.y = y;
+        self
+    }
+
+    /// x and y expressions of text, e.g. `(w-tw)/2` and `h-th-10`
+    pub fn xy(mut self, x: &str, y: &str) -> Self {
+        self.x = x.to_owned();
+        self.y = y.to_owned();
+        self
+    }
+
+    /// only draw text from `start` to `end`
+    pub fn between(mut self, start: &time::Duration, end: &time::Duration) -> Self {
+        self.enable = Some((*start, *end));
+        self
+    }
+
+    pub fn to_filter(&self) -> String {
+        let mut options = vec![];
+        match self.source {
+            TextSource::Text(ref text) => {
+                options.push(("expansion", "none".to_owned()));
+                options.push(("text", text.to_owned()));
+            }
+            TextSource::File(ref path) => {
+                options.push(("expansion", "none".to_owned()));
+                options.push(("textfile", path.to_owned()));
+            }
+            TextSource::Timecode { ref start, rate } => {
+                options.push(("timecode", start.to_owned()));
+                options.push(("timecode_rate", rate.to_string()));
+            }
+        }
+        if let Some(ref font_file) = self.font_file {
+            options.push(("fontfile", font_file.to_owned()));
+        }
+        if let Some(ref font) = self.font {
+            options.push(("font", font.to_owned()));
+        }
+        options.push(("fontsize", self.font_size.to_string()));
+        options.push(("fontcolor", self.font_color.to_owned()));
+        if let Some((ref color, border)) = self.background {
+            options.push(("box", "1".to_owned()));
+            options.push(("boxcolor", color.to_owned()));
+            options.push(("boxborderw", border.to_string()));
+        }
+        options.push(("x", self.x.to_owned()));
+        options.push(("y", self.y.to_owned()));
+        if let Some((start, end)) = self.enable {
+            options.push((
+                "enable",
+                format!("between(t,{},{})", start.as_secs_f64(), end.as_secs_f64()),
+            ));
+        }
+
+        let options: Vec<String> = options
+            .into_iter()
+            .map(|(key, value)| format!("{key}={}", escape_filter_value(&value)))
+            .collect();
+        format!("drawtext={}", options.join(":"))
+    }
+}
@@ -1,11 +1,44 @@
+use std::fmt;
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// failed to spawn ffmpeg or to access files
+    Io,
+    /// ffmpeg exited with failure or reported errors
+    Ffmpeg,
+    /// options given to builders are invalid or conflicting
+    InvalidOption,
+    /// streams of an input can not be selected as requested
+    StreamSelection,
+    /// output of ffmpeg could not be understood
+    UnexpectedOutput,
+    /// job was cancelled before it finished
+    Cancelled,
+    /// ffmpeg ran longer than its wall clock timeout and was killed
+    Timeout,
+    /// ffmpeg was stopped by its memory or cpu time limit
+    ResourceLimit,
+}
+
+#[derive(Clone, Debug)]
 pub struct Error {
+    pub kind: ErrorKind,
     pub msg: String,
 }
 
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}: {}", self.kind, self.msg)
+    }
+}
+
+impl std::error::Error for Error {}
+
 impl From<std::io::Error> for Error {
     fn from(e: std::io::Error) -> Self {
-        Self { msg: e.to_string() }
+        Self {
+            kind: ErrorKind::Io,
+            msg: e.to_string(),
+        }
     }
 }
@@ -1,15 +1,180 @@
-use std::{path::Path, time};
+use std::{fmt, path::Path, sync::Arc, time};
 
-use crate::{error::Error, output::FFmpegOutput, owned, utils::format_time};
+use crate::{
+    analysis::{self, StreamInfo},
+    error::{Error, ErrorKind},
+    output::FFmpegOutput,
+    owned,
+    utils::format_time,
+};
 
-#[derive(Clone, PartialEq, Debug)]
+#[derive(Clone, PartialEq, Debug, Default)]
 pub enum StreamType {
     Audio,
     Video,
+    #[default]
     Both,
+    Subtitle,
+    Data,
+    Attachment,
 }
 
+impl StreamType {
+    /// stream type letter of ffmpeg stream specifiers, `Both` matches all streams
+    pub(crate) fn specifier(&self) -> Option<&'static str> {
+        match self {
+            StreamType::Audio => Some("a"),
+            StreamType::Video => Some("v"),
+            StreamType::Both => None,
+            StreamType::Subtitle => Some("s"),
+            StreamType::Data => Some("d"),
+            StreamType::Attachment => Some("t"),
+        }
+    }
+}
+
+type StreamPredicate = Arc<dyn Fn(&StreamInfo) -> bool + Send + Sync>;
+
 #[derive(Clone)]
+enum StreamTarget {
+    Specifier(String),
+    Predicate(StreamPredicate),
+}
+
+/// selects streams of an input to be mapped to output, built on ffmpeg stream specifiers
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::{FFMpeg, StreamSelector, StreamType};
+/// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+/// FFMpeg::input("./movie.mkv")
+///     .select(StreamSelector::stream_type(StreamType::Video).index(0))
+///     .select(StreamSelector::stream_type(StreamType::Audio).language("eng"))
+///     .select(StreamSelector::stream_type(StreamType::Subtitle).optional())
+///     .select(StreamSelector::matching(|stream| stream.title.as_deref() == Some("Commentary")).exclude())
+///     .output()
+///     .save("./output/english.mkv")
+///     .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct StreamSelector {
+    target: StreamTarget,
+    exclude: bool,
+    optional: bool,
+}
+
+impl StreamSelector {
+    /// select all streams of `stream_type`, `StreamType::Both` selects every stream
+    pub fn stream_type(stream_type: StreamType) -> Self {
+        let specifier = stream_type.specifier().unwrap_or_default();
+        Self::specifier(specifier)
+    }
+
+    /// select streams by a raw ffmpeg stream specifier, e.g. `a:m:language:eng` or `v:0`
+    pub fn specifier(specifier: &str) -> Self {
+        Self {
+            target: StreamTarget::Specifier(specifier.to_owned()),
+            exclude: false,
+            optional: false,
+        }
+    }
+
+    /// select stream by its container id, e.g. PID of MPEG-TS
+    pub fn id(id: u64) -> Self {
+        Self::specifier(&format!("#{id}"))
+    }
+
+    /// select streams for which `predicate` returns true, input is probed
+    /// when args are built
+    pub fn matching(predicate: impl Fn(&StreamInfo) -> bool + Send + Sync + 'static) -> Self {
+        Self {
+            target: StreamTarget::Predicate(Arc::new(predicate)),
+            exclude: false,
+            optional: false,
+        }
+    }
+
+    /// narrow selection to the `index`th matching stream
+    pub fn index(self, index: u64) -> Self {
+        self.narrow(&index.to_string())
+    }
+
+    /// narrow selection to streams tagged with `language`, e.g. `eng`
+    pub fn language(self, language: &str) -> Self {
+        self.narrow(&format!("m:language:{language}"))
+    }
+
+    fn narrow(mut self, additional: &str) -> Self {
+        if let StreamTarget::Specifier(ref mut specifier) = self.target {
+            if !specifier.is_empty() {
+                specifier.push(':');
+            }
+            specifier.push_str(additional);
+        }
+        self
+    }
+
+    /// remove matching streams from what other selectors of this input mapped
+    pub fn exclude(mut self) -> Self {
+        self.exclude = true;
+        self
+    }
+
+    /// don't fail when no stream matches
+    pub fn optional(mut self) -> Self {
+        self.optional = true;
+        self
+    }
+
+    fn build_maps(&self, file: &str, idx: usize) -> Result<Vec<String>, Error> {
+        let negative = if self.exclude { "-" } else { "" };
+        let optional = if self.optional { "?" } else { "" };
+        let specifiers = match self.target {
+            StreamTarget::Specifier(ref specifier) if specifier.is_empty() => {
+                vec![format!("{negative}{idx}{optional}")]
+            }
+            StreamTarget::Specifier(ref specifier) => {
+                vec![format!("{negative}{idx}:{specifier}{optional}")]
+            }
+            StreamTarget::Predicate(ref predicate) => {
+                let info = analysis::probe(file)?;
+                let matched: Vec<String> = info
+                    .streams
+                    .iter()
+                    .filter(|stream| predicate(stream))
+                    .map(|stream| format!("{negative}{idx}:{}", stream.index))
+                    .collect();
+                if matched.is_empty() && !self.optional && !self.exclude {
+                    return Err(Error {
+                        kind: ErrorKind::StreamSelection,
+                        msg: format!("no stream of {file} matches selector"),
+                    });
+                }
+                matched
+            }
+        };
+        Ok(specifiers
+            .into_iter()
+            .flat_map(|specifier| owned!["-map", &specifier])
+            .collect())
+    }
+}
+
+impl fmt::Debug for StreamSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let target = match self.target {
+            StreamTarget::Specifier(ref specifier) => specifier.as_str(),
+            StreamTarget::Predicate(_) => "<predicate>",
+        };
+        f.debug_struct("StreamSelector")
+            .field("target", &target)
+            .field("exclude", &self.exclude)
+            .field("optional", &self.optional)
+            .finish()
+    }
+}
+
+#[derive(Clone, Default)]
 pub struct FFMpegInput {
     pub(crate) input_file: Option<String>,
     pub(crate) stream_type: StreamType,
@@ -20,11 +185,12 @@ pub struct FFMpegInput {
     pub(crate) end_time: Option<String>,
     pub(crate) stream_index: Option<u64>,
     pub(crate) format: Option<String>,
+    pub(crate) selectors: Vec<StreamSelector>,
 }
 
 impl FFMpegInput {
     pub fn new() -> Self {
-        return Self {
+        Self {
             input_file: None,
             stream_type: StreamType::Both,
             vcodec: None,
@@ -34,7 +200,8 @@ impl FFMpegInput {
             end_time: None,
             stream_index: None,
             format: None,
-        };
+            selectors: vec![],
+        }
     }
 
     pub fn format(mut self, format: &str) -> Self {
@@ -76,16 +243,85 @@ impl FFMpegInput {
         self
     }
 
+    pub fn only_subtitle(mut self) -> Self {
+        self.stream_type = StreamType::Subtitle;
+        self
+    }
+
+    /// take the `stream_index`th stream of chosen stream type, building args
+    /// fails with `ErrorKind::StreamSelection` when no stream type is chosen
     pub fn take_stream(mut self, stream_index: u64) -> Self {
-        assert_ne!(self.stream_type, StreamType::Both, "must specify video/audio stream by using .only_audio() or .only_video() before take stream");
         self.stream_index = Some(stream_index);
         self
     }
 
+    /// add a stream selector, selected streams are mapped to output along with
+    /// streams chosen by stream type. an input with only excluding selectors
+    /// maps all its other streams
+    ///
+    /// ffmpeg picks a single best video and audio stream only when no stream
+    /// is mapped explicitly, so once any input selects streams, inputs left to
+    /// defaults map all their video and audio streams
+    pub fn select(mut self, selector: StreamSelector) -> Self {
+        self.selectors.push(selector);
+        self
+    }
+
+    /// whether this input maps its streams explicitly instead of relying on
+    /// automatic stream selection of ffmpeg
+    pub(crate) fn has_explicit_maps(&self) -> bool {
+        self.stream_type != StreamType::Both || !self.selectors.is_empty()
+    }
+
+    /// `-map` args of this input as `idx`th input, `implicit_maps` are used for
+    /// `StreamType::Both` when automatic stream selection is disabled
+    pub(crate) fn build_maps(
+        &self,
+        idx: usize,
+        implicit_maps: &[&str],
+    ) -> Result<Vec<String>, Error> {
+        let mut maps = owned![];
+        match (self.stream_type.specifier(), self.stream_index) {
+            (None, Some(_)) => {
+                return Err(Error {
+                    kind: ErrorKind::StreamSelection,
+                    msg: "must specify stream type by using .only_audio(), .only_video() or .stream_type() before take stream".to_owned(),
+                });
+            }
+            (Some(stream_type), Some(stream_index)) => {
+                maps.append(&mut owned![
+                    "-map",
+                    &format!("{idx}:{stream_type}:{stream_index}")
+                ]);
+            }
+            (Some(stream_type), None) => {
+                maps.append(&mut owned!["-map", &format!("{idx}:{stream_type}")]);
+            }
+            (None, None) if self.selectors.is_empty() => {
+                for stream in implicit_maps {
+                    maps.append(&mut owned!["-map", &format!("{idx}:{stream}")]);
+                }
+            }
+            // exclusions remove streams from what was mapped, start from all of them
+            (None, None) if self.selectors.iter().all(|selector| selector.exclude) => {
+                maps.append(&mut owned!["-map", &idx.to_string()]);
+            }
+            (None, None) => (),
+        }
+        let file = self.get_input_file()?;
+        for selector in &self.selectors {
+            maps.append(&mut selector.build_maps(&file, idx)?);
+        }
+        Ok(maps)
+    }
+
     /// set stream type for input file:
     /// StreamType::Audio: take only audio stream from input file
     /// StreamType::Video: take only video stream from input file
     /// StreamType::Both: take video and audio stream from input file
+    /// StreamType::Subtitle: take only subtitle stream from input file
+    /// StreamType::Data: take only data stream from input file
+    /// StreamType::Attachment: take only attachment (e.g. font) stream from input file
     pub fn stream_type(mut self, stream_type: StreamType) -> Self {
         self.stream_type = stream_type;
         self
@@ -110,6 +346,7 @@ impl FFMpegInput {
             .input_file
             .as_ref()
             .ok_or(Error {
+                kind: ErrorKind::InvalidOption,
                 msg: "input file is not set".to_owned(),
             })?
             .clone();
@@ -160,7 +397,7 @@ impl FFMpegInput {
 
     pub fn output(&self) -> FFmpegOutput {
         let input = FFMpegMultipleInput::new(self);
-        return FFmpegOutput::new(input);
+        FFmpegOutput::new(input)
     }
 
     pub fn merge(&self, anthor_input: &FFMpegInput) -> FFMpegMultipleInput {
@@ -168,11 +405,68 @@ impl FFMpegInput {
     }
 }
 
+/// `xfade` transition type
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TransitionKind {
+    Fade,
+    FadeBlack,
+    FadeWhite,
+    Dissolve,
+    WipeLeft,
+    WipeRight,
+    SlideLeft,
+    SlideRight,
+    CircleOpen,
+    CircleClose,
+    Radial,
+    Pixelize,
+    /// any other transition name supported by `xfade`
+    Custom(String),
+}
+
+impl TransitionKind {
+    fn name(&self) -> &str {
+        match self {
+            TransitionKind::Fade => "fade",
+            TransitionKind::FadeBlack => "fadeblack",
+            TransitionKind::FadeWhite => "fadewhite",
+            TransitionKind::Dissolve => "dissolve",
+            TransitionKind::WipeLeft => "wipeleft",
+            TransitionKind::WipeRight => "wiperight",
+            TransitionKind::SlideLeft => "slideleft",
+            TransitionKind::SlideRight => "slideright",
+            TransitionKind::CircleOpen => "circleopen",
+            TransitionKind::CircleClose => "circleclose",
+            TransitionKind::Radial => "radial",
+            TransitionKind::Pixelize => "pixelize",
+            TransitionKind::Custom(name) => name,
+        }
+    }
+}
+
+/// transition between two joined clips, clips overlap for `duration`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transition {
+    pub kind: TransitionKind,
+    pub duration: time::Duration,
+}
+
+impl Transition {
+    pub fn new(kind: TransitionKind, duration: &time::Duration) -> Self {
+        Self {
+            kind,
+            duration: *duration,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub enum MergeStrategy {
     Merge,
     Concat,
 }
 
+#[derive(Clone)]
 pub struct FFMpegMultipleInput {
     pub(crate) inputs: Vec<FFMpegInput>,
     pub(crate) merge_strategy: MergeStrategy,
@@ -199,13 +493,166 @@ impl FFMpegMultipleInput {
     ///     .save("./output/concat_output.mp4");
     /// ```
     pub fn concat(inputs: &[&str]) -> Self {
-        let inputs: Vec<FFMpegInput> = inputs.into_iter().map(|i| FFMpegInput::input(*i)).collect();
+        let inputs: Vec<FFMpegInput> = inputs.iter().map(|i| FFMpegInput::input(*i)).collect();
         Self {
             inputs,
             merge_strategy: MergeStrategy::Concat,
         }
     }
 
+    /// join inputs by order with a transition between each pair, video is
+    /// joined by `xfade` and audio by `acrossfade`, offsets are computed from
+    /// probed durations
+    ///
+    /// `transitions` holds either one transition used for every pair, or one
+    /// for each pair. all inputs are normalised to size and frame rate of the
+    /// first one, audio is only kept when every input has audio
+    ///
+    /// each transition must be shorter than the shortest clip. the returned
+    /// output is built on `filter_complex`, so `resize`, `crop` and video or
+    /// audio filters can not be added to it
+    ///
+    /// samples:
+    /// ```
+    /// use ffmpeg_cli_utils::{FFMpegMultipleInput, Transition, TransitionKind};
+    /// use std::time;
+    /// # use ffmpeg_cli_utils::FFMpeg;
+    /// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+    /// let videos = ["./sample.mp4", "./sample1.mp4"];
+    /// let fade = Transition::new(TransitionKind::Fade, &time::Duration::from_secs(1));
+    /// FFMpegMultipleInput::concat_with_transitions(&videos, &[fade])
+    ///     .unwrap()
+    ///     .save("./output/crossfaded.mp4")
+    ///     .unwrap();
+    /// ```
+    pub fn concat_with_transitions(
+        inputs: &[&str],
+        transitions: &[Transition],
+    ) -> Result<FFmpegOutput, Error> {
+        if inputs.len() < 2 {
+            return Err(Error {
+                kind: ErrorKind::InvalidOption,
+                msg: "at least two inputs are required to join with transitions".to_owned(),
+            });
+        }
+        let transitions: Vec<&Transition> = match transitions.len() {
+            1 => vec![&transitions[0]; inputs.len() - 1],
+            n if n == inputs.len() - 1 => transitions.iter().collect(),
+            n => {
+                return Err(Error {
+                    kind: ErrorKind::InvalidOption,
+                    msg: format!(
+                        "expected 1 or {} transitions for {} inputs, got {n}",
+                        inputs.len() - 1,
+                        inputs.len()
+                    ),
+                });
+            }
+        };
+        if transitions
+            .iter()
+            .any(|transition| transition.duration.is_zero())
+        {
+            return Err(Error {
+                kind: ErrorKind::InvalidOption,
+                msg: "transition duration must be greater than zero".to_owned(),
+            });
+        }
+
+        let infos = inputs
+            .iter()
+            .map(|input| analysis::probe(input))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let durations = infos
+            .iter()
+            .zip(inputs)
+            .map(|(info, input)| {
+                info.duration.ok_or(Error {
+                    kind: ErrorKind::UnexpectedOutput,
+                    msg: format!("failed to get duration of {input}"),
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        // xfade offset of a transition as long as a clip would not move forward
+        let shortest = durations.iter().min().copied().unwrap_or_default();
+        if let Some(transition) = transitions
+            .iter()
+            .find(|transition| transition.duration >= shortest)
+        {
+            return Err(Error {
+                kind: ErrorKind::InvalidOption,
+                msg: format!(
+                    "transition of {:?} must be shorter than the shortest clip of {shortest:?}",
+                    transition.duration
+                ),
+            });
+        }
+        let first_video = infos[0]
+            .streams
+            .iter()
+            .find(|stream| stream.stream_type == StreamType::Video)
+            .ok_or(Error {
+                kind: ErrorKind::StreamSelection,
+                msg: format!("no video stream found in {}", inputs[0]),
+            })?;
+        let (width, height) = (
+            first_video.width.unwrap_or(1280),
+            first_video.height.unwrap_or(720),
+        );
+        // exact rate, a rounded one like 23.98 drifts from the source
+        let fps = first_video
+            .r_frame_rate
+            .map(|(num, den)| format!("{num}/{den}"))
+            .unwrap_or_else(|| "30".to_owned());
+        let with_audio = infos.iter().all(|info| {
+            info.streams
+                .iter()
+                .any(|stream| stream.stream_type == StreamType::Audio)
+        });
+
+        let mut graph = vec![];
+        for i in 0..inputs.len() {
+            graph.push(format!(
+                "[{i}:v]scale={width}:{height}:force_original_aspect_ratio=decrease,pad={width}:{height}:(ow-iw)/2:(oh-ih)/2,setsar=1,fps={fps},format=yuv420p,settb=AVTB[v{i}]"
+            ));
+            if with_audio {
+                graph.push(format!(
+                    "[{i}:a]aformat=sample_rates=48000:channel_layouts=stereo[a{i}]"
+                ));
+            }
+        }
+
+        let mut offset = 0.0;
+        let (mut video, mut audio) = ("[v0]".to_owned(), "[a0]".to_owned());
+        for (i, transition) in transitions.iter().enumerate() {
+            let duration = transition.duration.as_secs_f64();
+            offset += durations[i].as_secs_f64() - duration;
+            let next = i + 1;
+            graph.push(format!(
+                "{video}[v{next}]xfade=transition={}:duration={duration}:offset={offset}[vx{next}]",
+                transition.kind.name()
+            ));
+            video = format!("[vx{next}]");
+            if with_audio {
+                graph.push(format!("{audio}[a{next}]acrossfade=d={duration}[ax{next}]"));
+                audio = format!("[ax{next}]");
+            }
+        }
+
+        let inputs: Vec<FFMpegInput> = inputs.iter().map(FFMpegInput::input).collect();
+        let mut maps = vec!["-map".to_owned(), video];
+        if with_audio {
+            maps.append(&mut owned!["-map", &audio]);
+        }
+        Ok(Self {
+            inputs,
+            merge_strategy: MergeStrategy::Merge,
+        }
+        .output()
+        .filter_complex(&graph.join(";"))
+        .args(maps))
+    }
+
     pub fn merge(one: &FFMpegInput, two: &FFMpegInput) -> Self {
         Self {
             inputs: vec![one.clone(), two.clone()],
@@ -213,11 +660,11 @@ impl FFMpegMultipleInput {
         }
     }
     pub fn append(&mut self, inputs: Vec<&FFMpegInput>) {
-        let mut inputs: Vec<FFMpegInput> = inputs.into_iter().map(|input| input.clone()).collect();
+        let mut inputs: Vec<FFMpegInput> = inputs.into_iter().cloned().collect();
         self.inputs.append(&mut inputs);
     }
 
     pub fn output(self) -> FFmpegOutput {
-        return FFmpegOutput::new(self);
+        FFmpegOutput::new(self)
     }
 }
@@ -4,12 +4,18 @@
 //! ## Intro
 
 //! ### Simple input/output
-//! ffmpeg_utils_rs depends on tokio runtime
+//! `save` and `blocking_stream` are plain blocking calls and work without
+//! any async runtime. `async_save` and `stream` run on tokio with the default
+//! `async` (`runtime-tokio`) feature, or on any executor such as async-std or
+//! smol with `default-features = false, features = ["runtime-async-process"]`.
+//! build with `default-features = false` alone to drop async support entirely.
+//!
 //! before running codes below, ffmpeg should be placed in $PATH,
 //! or you can either configure ffmpeg static binary by using `set_binary_path`
 //! or set an env FFMEPG_BIN=path/to/ffmpeg
 
 //! ```rust
+//! # use ffmpeg_cli_utils::FFMpeg;
 //! fn main() {
 //!   let ffmpeg = FFMpeg::new();
 //!   ffmpeg
@@ -23,12 +29,14 @@
 //! ### Resize to (width, height)
 
 //! ```rust
+//! # use ffmpeg_cli_utils::FFMpeg;
 //! fn main() {
 //!   let ffmpeg = FFMpeg::new();
 //!   ffmpeg
 //!       .input_file("./sample.mp4")
+//!       .output()
 //!       .resize(1280, 720)
-//!       .save("./output_720p.mp4")
+//!       .save("./output/output_720p.mp4")
 //!       .unwrap();
 //! }
 //! ```
@@ -36,11 +44,12 @@
 //! ### Return AsyncRead and use as stream
 
 //! ```rust
+//! # use ffmpeg_cli_utils::FFMpeg;
 //! #[tokio::main]
 //! async fn main() {
 //!   let ffmpeg = FFMpeg::new();
 //!   let mut reader = ffmpeg.input_file("./sample.mp4").output().resize(-2, 320).stream().unwrap();
-//!   let mut output_file = tokio::fs::File::create("./output-stream.mp4")
+//!   let mut output_file = tokio::fs::File::create("./output/output-stream.mp4")
 //!       .await
 //!       .unwrap();
 //!   tokio::io::copy(&mut reader, &mut output_file)
@@ -49,17 +58,24 @@
 //! }
 //! ```
 
-//! stream is useful in some realtime cases, e.g. http response:
+//! stream is useful in some realtime cases, e.g. http response. the reader is
+//! also a `Stream` of `bytes::Bytes` chunks, so it can be handed over as is:
 
 //! ```rust
+//! # use ffmpeg_cli_utils::FFMpeg;
+//! # struct HttpResponse;
+//! # #[allow(non_snake_case)]
+//! # impl HttpResponse {
+//! #     fn Ok() -> Self { HttpResponse }
+//! #     fn streaming<S: futures::Stream>(self, _: S) -> Self { self }
+//! # }
 //! fn some_route() -> HttpResponse {
-//!   let mut reader = ffmpeg
-//!       .set_binary_path("./ffmpeg")
+//!   let reader = FFMpeg::new()
 //!       .input_file("./sample.mp4")
+//!       .output()
 //!       .stream()
 //!       .unwrap();
-//!   let reader_stream = tokio_util::io::ReaderStream::new(reader);
-//!   HttpResponse::Ok().streaming(reader_stream)
+//!   HttpResponse::Ok().streaming(reader)
 //! }
 //! ```
 
@@ -68,12 +84,14 @@
 //! ### Set bitrate
 
 //! ```rust
-//! fn some_route() -> HttpResponse {
+//! # use ffmpeg_cli_utils::FFMpeg;
+//! fn main() {
 //!   let ffmpeg = FFMpeg::new();
 //!   ffmpeg
 //!       .input_file("./sample.mp4")
-//!       .bitrate(1000)
-//!       .save("./output_720p.mp4")
+//!       .output()
+//!       .set_bitrate(1000)
+//!       .save("./output/output_bitrate.mp4")
 //!       .unwrap();
 //! }
 //! ```
@@ -81,18 +99,22 @@
 //! ### Inspect ffmpeg args
 
 //! ```rust
-//! fn some_route() -> HttpResponse {
+//! # use ffmpeg_cli_utils::FFMpeg;
+//! fn main() {
 //!   let ffmpeg = FFMpeg::new();
 //!   let args = ffmpeg
 //!       .input_file("./sample.mp4")
-//!       .bitrate(1000)
-//!       .build_args(Some("/path/to/output_file"));
+//!       .output()
+//!       .set_bitrate(1000)
+//!       .build_args(Some("/path/to/output_file".to_owned()));
 //! }
 //! ```
 
 //! ### Combine multiple input
 
 //! ```rust
+//! # use ffmpeg_cli_utils::FFMpeg;
+//! # use std::time;
 //! fn main() {
 //!   let start_time = time::Duration::from_secs(30);
 //!   let end_time = time::Duration::from_secs(60);
@@ -106,10 +128,10 @@
 //!   let input2 = FFMpeg::new().input_file("./sample.mp4").only_video();
 
 //!   input1
-//!       .concat(&input2)
+//!       .merge(&input2)
 //!       .output()
 //!       .resize(-2, 480)
-//!       .save("./combination_output.mp4")
+//!       .save("./output/combination_output.mp4")
 //!       .unwrap();
 //! }
 //! ```
@@ -118,13 +140,31 @@ mod error;
 mod input;
 mod macros;
 mod output;
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-process"))]
+mod runtime;
 mod utils;
 
 use std::sync::Mutex;
 
+pub use error::{Error, ErrorKind};
 pub use input::FFMpegInput;
 pub use input::FFMpegMultipleInput;
+pub use input::StreamSelector;
+pub use input::StreamType;
+pub use input::Transition;
+pub use input::TransitionKind;
+pub use output::OverwritePolicy;
+pub mod analysis;
+pub mod audio;
+pub mod batch;
+pub mod container;
+pub mod drawtext;
+pub mod limits;
+pub mod metadata;
+pub mod queue;
+pub mod subtitle;
 pub mod tools;
+pub mod verify;
 
 pub struct FFMpeg {}
 use lazy_static::lazy_static;
@@ -132,26 +172,26 @@ use lazy_static::lazy_static;
 lazy_static! {
     pub static ref BIN_PATH: Mutex<String> = {
         let default = String::from("ffmpeg");
-        let mutex = Mutex::new(default);
-        mutex
+        Mutex::new(default)
     };
 }
 
-///! FFMpeg cli utils
-///! samples:
-///! ```
-///! use ffmpeg_cli_utils::FFMpeg;
-///! FFMpeg::set_ffmpeg_bin("./ffmpeg"); //! not necessary
+/// FFMpeg cli utils
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::FFMpeg;
+/// FFMpeg::set_ffmpeg_bin("./ffmpeg"); // not necessary
 ///
-///! let ffmpeg = FFMpeg::new();
-///! ffmpeg
-///!     .input_file("./sample.mp4")
-///!     .output()
-///!     .resize(-2, 480)
-///!     .save("./output/output_480p.mp4")
-///!     .unwrap();
-///! ```
+/// let ffmpeg = FFMpeg::new();
+/// ffmpeg
+///     .input_file("./sample.mp4")
+///     .output()
+///     .resize(-2, 480)
+///     .save("./output/output_480p.mp4")
+///     .unwrap();
+/// ```
 impl FFMpeg {
+    #[allow(clippy::new_ret_no_self)]
     pub fn new() -> FFMpegInput {
         FFMpegInput::new()
     }
@@ -162,6 +202,49 @@ impl FFMpeg {
         let mut s = BIN_PATH.lock().unwrap();
         s.clone_from(&bin_path.to_owned());
     }
+    /// list names of all filters supported by the configured ffmpeg binary
+    pub fn filters() -> Result<Vec<String>, Error> {
+        let output = std::process::Command::new(Self::get_ffmpeg_bin())
+            .args(["-hide_banner", "-filters"])
+            .output()?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        // e.g. " TSC psnr              VV->V      Calculate the PSNR between two video streams."
+        let filters = stdout
+            .lines()
+            .filter_map(|line| {
+                let mut columns = line.split_whitespace();
+                let (_, name, io) = (columns.next()?, columns.next()?, columns.next()?);
+                io.contains("->").then(|| name.to_owned())
+            })
+            .collect();
+        Ok(filters)
+    }
+    /// check if the configured ffmpeg binary is built with filter `name`
+    pub fn has_filter(name: &str) -> bool {
+        Self::filters()
+            .map(|filters| filters.iter().any(|filter| filter == name))
+            .unwrap_or(false)
+    }
+    /// list names of all muxers (output formats) supported by the configured ffmpeg binary
+    pub fn muxers() -> Result<Vec<String>, Error> {
+        let output = std::process::Command::new(Self::get_ffmpeg_bin())
+            .args(["-hide_banner", "-muxers"])
+            .output()?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        // e.g. "  E mp4             MP4 (MPEG-4 Part 14)"
+        let muxers = stdout
+            .lines()
+            .skip_while(|line| !line.trim_start().starts_with("--"))
+            .skip(1)
+            .filter_map(|line| {
+                let mut columns = line.split_whitespace();
+                let (flags, names) = (columns.next()?, columns.next()?);
+                flags.contains('E').then_some(names)
+            })
+            .flat_map(|names| names.split(',').map(str::to_owned))
+            .collect();
+        Ok(muxers)
+    }
     pub(crate) fn get_ffmpeg_bin() -> String {
         let s = BIN_PATH.lock().unwrap();
         s.to_owned()
@@ -171,7 +254,19 @@ impl FFMpeg {
 #[cfg(test)]
 mod tests {
 
-    use crate::{input::FFMpegMultipleInput, tools, FFMpeg};
+    use crate::{
+        analysis, audio, batch,
+        container::StreamingContainer,
+        drawtext::DrawText,
+        input::FFMpegMultipleInput,
+        metadata::{Chapter, MetadataSource},
+        queue::{Job, JobEvent, JobQueue, RetryPolicy},
+        subtitle,
+        subtitle::SubtitleTrack,
+        tools, utils,
+        verify::Verification,
+        ErrorKind, FFMpeg, OverwritePolicy, StreamSelector, StreamType, Transition, TransitionKind,
+    };
     use std::{fs, process, str::FromStr, sync::Once, time};
 
     static ONCE: Once = Once::new();
@@ -188,7 +283,7 @@ mod tests {
                 println!("sample video is not existed, start downloading from {sample_video_url}...");
                 let mut child = process::Command::new("curl");
                 child
-                    .args(&[
+                    .args([
                         sample_video_url,
                         "-o",
                         "sample.mp4",
@@ -229,7 +324,7 @@ mod tests {
 
                 let mut child = process::Command::new("curl");
                 child
-                    .args(&[
+                    .args([
                         "-L",
                         &binary_download_url,
                         "-o",
@@ -242,13 +337,14 @@ mod tests {
                 if cfg!(target_os = "linux") || cfg!(target_os = "macos") {
                     use std::os::unix::prelude::PermissionsExt;
                     let mut perm = fs::metadata("./ffmpeg").unwrap().permissions();
-                    perm.set_mode(0x744);
+                    perm.set_mode(0o744);
                     fs::set_permissions("./ffmpeg", perm).unwrap();
                 }
             }
         });
     }
 
+    #[cfg(feature = "runtime-tokio")]
     #[tokio::test]
     async fn output_to_file() {
         init();
@@ -272,6 +368,328 @@ mod tests {
             .unwrap();
     }
 
+    #[test]
+    fn streaming_containers() {
+        let has = |args: &[String], pair: [&str; 2]| args.windows(2).any(|w| w == pair);
+
+        let mut output = FFMpeg::input("./sample.mp4").output();
+        let args = output.build_args(None).unwrap();
+        assert!(has(&args, ["-f", "mp4"]));
+        assert!(has(
+            &args,
+            ["-movflags", "frag_keyframe+empty_moov+default_base_moof"]
+        ));
+        assert_eq!(output.content_type(), Some("video/mp4"));
+
+        let mut output = FFMpeg::input("./sample.mp4").output().format("mpegts");
+        let args = output.build_args(None).unwrap();
+        assert!(has(&args, ["-f", "mpegts"]));
+        assert!(!args.iter().any(|arg| arg == "-movflags"));
+        assert_eq!(output.content_type(), Some("video/mp2t"));
+
+        let mut output = FFMpeg::input("./sample.mp4")
+            .output()
+            .streaming_container(StreamingContainer::WebM);
+        let args = output.build_args(None).unwrap();
+        assert!(has(&args, ["-f", "webm"]) && has(&args, ["-live", "1"]));
+        assert_eq!(output.content_type(), Some("video/webm"));
+
+        let container =
+            StreamingContainer::fragmented_mp4().fragment_duration(time::Duration::from_secs(2));
+        let args = FFMpeg::input("./sample.mp4")
+            .output()
+            .streaming_container(container)
+            .build_args(None)
+            .unwrap();
+        assert!(has(&args, ["-frag_duration", "2000000"]));
+
+        let args = FFMpeg::input("./sample.mp4")
+            .output()
+            .build_args(Some("./output/faststart.mp4".to_owned()))
+            .unwrap();
+        assert!(has(&args, ["-movflags", "+faststart"]));
+        let args = FFMpeg::input("./sample.mp4")
+            .output()
+            .build_args(Some("./output/faststart.mkv".to_owned()))
+            .unwrap();
+        assert!(!args.iter().any(|arg| arg == "-movflags"));
+
+        init();
+        FFMpeg::input("./sample.mp4")
+            .output()
+            .resize(-2, 320)
+            .streaming_container(StreamingContainer::MpegTs)
+            .write_to(fs::File::create("./output/streaming.ts").unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn job_queue() {
+        drop(JobQueue::new(0).total_threads(2));
+
+        init();
+        let queue = JobQueue::new(1)
+            .total_threads(2)
+            .retry(RetryPolicy::new(1, time::Duration::from_millis(10)).retry_if(|_| true));
+        let events = queue.subscribe();
+        let first = queue.push(Job::new(
+            FFMpeg::input("./sample.mp4").output().timeout(3),
+            "./output/queue-first.mp4",
+        ));
+        let low = queue.push(
+            Job::new(
+                FFMpeg::input("./sample.mp4").output().timeout(1),
+                "./output/queue-low.mp4",
+            )
+            .priority(-1),
+        );
+        let high = queue.push(
+            Job::new(
+                FFMpeg::input("./sample.mp4").output().timeout(1),
+                "./output/queue-high.mp4",
+            )
+            .priority(1),
+        );
+        let failing = queue.push(Job::new(
+            FFMpeg::input("./not-exists.mp4").output(),
+            "./output/queue-failing.mp4",
+        ));
+        let cancelled = queue.push(
+            Job::new(
+                FFMpeg::input("./sample.mp4").output(),
+                "./output/queue-cancelled.mp4",
+            )
+            .priority(-10),
+        );
+        cancelled.cancel();
+        let _ = fs::remove_file("./output/queue-killed.mp4");
+        let killed = queue.push(
+            Job::new(
+                FFMpeg::input("./sample.mp4")
+                    .output()
+                    .args(vec!["-c:v", "libx264", "-preset", "veryslow"])
+                    .wall_timeout(time::Duration::from_millis(200)),
+                "./output/queue-killed.mp4",
+            )
+            .priority(-5),
+        );
+
+        first.wait().unwrap();
+        high.wait().unwrap();
+        low.wait().unwrap();
+        assert_eq!(failing.wait().unwrap_err().kind, ErrorKind::Ffmpeg);
+        assert_eq!(cancelled.wait().unwrap_err().kind, ErrorKind::Cancelled);
+        // output of a failed job never reaches its destination
+        assert_eq!(killed.wait().unwrap_err().kind, ErrorKind::Timeout);
+        assert!(!std::path::Path::new("./output/queue-killed.mp4").exists());
+
+        let events: Vec<_> = events.try_iter().collect();
+        let started: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                JobEvent::Started { id, attempt: 1 } => Some(*id),
+                _ => None,
+            })
+            .collect();
+        // first job may start before the rest are pushed, high always precedes low
+        let pos = |id| started.iter().position(|started| *started == id).unwrap();
+        assert!(pos(2) < pos(1));
+        assert!(!started.contains(&4));
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, JobEvent::Progress { id: 0, .. })));
+        assert!(events.iter().any(|event| matches!(
+            event,
+            JobEvent::Retrying {
+                id: 3,
+                attempt: 1,
+                ..
+            }
+        )));
+    }
+
+    #[test]
+    fn batch_transcode() {
+        use crate::utils::glob_match;
+        assert!(glob_match("*.mp4", "a.mp4"));
+        assert!(!glob_match("*.mp4", "dir/a.mp4"));
+        assert!(glob_match("**/*.mp4", "a.mp4"));
+        assert!(glob_match("**/*.mp4", "dir/sub/a.mp4"));
+        assert!(glob_match("drafts/**", "drafts/x/a.mov"));
+        assert!(glob_match("clip_??.mov", "clip_01.mov"));
+        assert!(!glob_match("clip_??.mov", "clip_1.mov"));
+
+        init();
+        let input_dir = tempfile::tempdir().unwrap();
+        let output_dir = input_dir.path().join("out");
+        fs::create_dir(input_dir.path().join("drafts")).unwrap();
+        for file in ["a.mp4", "b.mp4", "drafts/c.mp4"] {
+            fs::copy("./sample.mp4", input_dir.path().join(file)).unwrap();
+        }
+        fs::write(input_dir.path().join("broken.mp4"), "not a video").unwrap();
+        fs::write(input_dir.path().join("notes.txt"), "").unwrap();
+
+        let batch = batch::transcode_dir(
+            &input_dir.path().to_string_lossy(),
+            &output_dir.to_string_lossy(),
+        )
+        .include("*.mp4")
+        .exclude("drafts/**")
+        .template("{stem}_{height}p.{ext}")
+        .height(240)
+        .extension("mkv")
+        .parallel(2)
+        .output(|output| output.timeout(1));
+        let report = batch.run().unwrap();
+        assert_eq!(
+            (report.done(), report.skipped(), report.failed()),
+            (2, 0, 1)
+        );
+        assert!(output_dir.join("a_240p.mkv").exists());
+        assert!(!output_dir.join("drafts").exists());
+
+        // outputs are newer than inputs now
+        let report = batch.run().unwrap();
+        assert_eq!(
+            (report.done(), report.skipped(), report.failed()),
+            (0, 2, 1)
+        );
+        assert_eq!(report.to_tsv().lines().count(), 4);
+    }
+
+    #[test]
+    fn atomic_save_and_overwrite_policy() {
+        init();
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("out.mp4").to_string_lossy().into_owned();
+
+        // failure leaves neither destination nor temp file behind
+        FFMpeg::input("./not-exists.mp4")
+            .output()
+            .save(&file)
+            .unwrap_err();
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        FFMpeg::input("./sample.mp4")
+            .output()
+            .timeout(1)
+            .save(&file)
+            .unwrap();
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+        let saved = fs::read(&file).unwrap();
+
+        let err = FFMpeg::input("./sample.mp4")
+            .output()
+            .timeout(2)
+            .overwrite(OverwritePolicy::Fail)
+            .save(&file)
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Io);
+        assert_eq!(fs::read(&file).unwrap(), saved);
+
+        let err = FFMpeg::input("./sample.mp4")
+            .output()
+            .timeout(2)
+            .overwrite(OverwritePolicy::Fail)
+            .in_place()
+            .save(&file)
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Ffmpeg);
+        assert_eq!(fs::read(&file).unwrap(), saved);
+    }
+
+    #[test]
+    fn verify_output() {
+        init();
+        let mut output = FFMpeg::input("./sample.mp4")
+            .start_time(&time::Duration::from_secs(10))
+            .end_time(&time::Duration::from_secs(14))
+            .output()
+            .resize(-2, 240)
+            .args(vec!["-c:v", "libx264", "-c:a", "aac"]);
+        output.save("./output/verify_output.mp4").unwrap();
+
+        let report = output
+            .verify(
+                "./output/verify_output.mp4",
+                &Verification::new().stream_count(2).full_decode(),
+            )
+            .unwrap();
+        assert!(report.passed(), "{report}");
+        let names: Vec<_> = report.checks.iter().map(|check| check.name).collect();
+        assert_eq!(
+            names,
+            [
+                "duration",
+                "stream count",
+                "resolution",
+                "video codec",
+                "audio codec"
+            ]
+        );
+
+        let report = output
+            .verify(
+                "./output/verify_output.mp4",
+                &Verification::new()
+                    .duration(time::Duration::from_secs(10))
+                    .video_codec("hevc"),
+            )
+            .unwrap();
+        assert!(!report.passed());
+        let failures: Vec<_> = report.failures().map(|check| check.name).collect();
+        assert_eq!(failures, ["duration", "video codec"]);
+
+        let report = FFMpeg::input("./sample.mp4")
+            .output()
+            .resize(0, 240)
+            .verify(
+                "./output/verify_output.mp4",
+                &Verification::new().skip_duration(),
+            )
+            .unwrap();
+        assert!(report.passed(), "{report}");
+    }
+
+    #[test]
+    fn resource_limits() {
+        let has = |args: &[String], pair: [&str; 2]| args.windows(2).any(|w| w == pair);
+        let args = FFMpeg::input("./sample.mp4")
+            .output()
+            .threads(2)
+            .filter_threads(1)
+            .build_args(Some("./output/limits.mp4".to_owned()))
+            .unwrap();
+        assert!(has(&args, ["-threads", "2"]) && has(&args, ["-filter_threads", "1"]));
+
+        init();
+        let err = FFMpeg::input("./sample.mp4")
+            .output()
+            .nice(20)
+            .save("./output/limits.mp4")
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidOption);
+
+        let err = FFMpeg::input("./sample.mp4")
+            .output()
+            .args(vec!["-c:v", "libx264", "-preset", "veryslow"])
+            .wall_timeout(time::Duration::from_millis(200))
+            .save("./output/limits.mp4")
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Timeout);
+
+        FFMpeg::input("./sample.mp4")
+            .output()
+            .timeout(1)
+            .threads(1)
+            .nice(10)
+            .memory_limit(4 << 30)
+            .cpu_time_limit(time::Duration::from_secs(60))
+            .wall_timeout(time::Duration::from_secs(60))
+            .save("./output/limits.mp4")
+            .unwrap();
+    }
+
     #[test]
     fn merge_videos() {
         init();
@@ -348,6 +766,7 @@ mod tests {
             .unwrap();
     }
 
+    #[cfg(feature = "runtime-tokio")]
     #[tokio::test]
     async fn output_to_stream() {
         init();
@@ -370,6 +789,152 @@ mod tests {
         println!("stream time cost: {d:?}");
     }
 
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test]
+    async fn stream_slow_consumer_and_early_drop() {
+        use futures::StreamExt;
+        init();
+        let expected = FFMpeg::input("./sample.mp4")
+            .output()
+            .resize(-2, 320)
+            .blocking_stream()
+            .unwrap();
+        let expected = std::io::Read::bytes(expected).count();
+
+        // consumer slower than ffmpeg still gets every byte, then a clean end
+        let mut reader = FFMpeg::input("./sample.mp4")
+            .output()
+            .resize(-2, 320)
+            .set_buffer_size(4096)
+            .stream()
+            .unwrap();
+        let mut size = 0;
+        while let Some(chunk) = reader.next().await {
+            size += chunk.unwrap().len();
+            tokio::time::sleep(time::Duration::from_millis(1)).await;
+        }
+        assert_eq!(size, expected);
+        assert!(reader.next().await.is_none());
+
+        // dropping the reader halfway must not hang nor leak ffmpeg
+        let mut reader = FFMpeg::input("./sample.mp4").output().stream().unwrap();
+        let first = reader.next().await.unwrap().unwrap();
+        assert!(!first.is_empty());
+        drop(reader);
+
+        // ffmpeg failure is the last item of the stream
+        let mut reader = FFMpeg::input("./not-exists.mp4").output().stream().unwrap();
+        let mut last = None;
+        while let Some(chunk) = reader.next().await {
+            last = Some(chunk);
+        }
+        assert!(last.unwrap().is_err());
+    }
+
+    #[test]
+    fn write_to_sink() {
+        let err = FFMpeg::input("./sample.mp4")
+            .output()
+            .write_to(Vec::new())
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidOption);
+
+        init();
+        assert!(FFMpeg::muxers().unwrap().iter().any(|muxer| muxer == "mp4"));
+        let err = FFMpeg::input("./sample.mp4")
+            .output()
+            .format("not-a-format")
+            .write_to(Vec::new())
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidOption);
+
+        let mut sink = Vec::new();
+        let result = FFMpeg::input("./sample.mp4")
+            .output()
+            .resize(-2, 320)
+            .format("mp4")
+            .write_to(&mut sink)
+            .unwrap();
+        assert_eq!(result.bytes_written, sink.len() as u64);
+        assert!(result.bytes_written > 0);
+        assert!(result.status.success());
+        assert!(result.stderr.is_empty());
+
+        let err = FFMpeg::input("./not-exists.mp4")
+            .output()
+            .format("mp4")
+            .write_to(Vec::new())
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Ffmpeg);
+
+        let err = FFMpeg::input("./sample.mp4")
+            .output()
+            .args(vec!["-c:v", "libx264", "-preset", "veryslow"])
+            .wall_timeout(time::Duration::from_millis(200))
+            .format("mp4")
+            .write_to(Vec::new())
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::Timeout);
+    }
+
+    #[cfg(feature = "runtime-tokio")]
+    #[tokio::test]
+    async fn async_write_to_sink() {
+        init();
+        let mut sink = futures::io::Cursor::new(Vec::new());
+        let result = FFMpeg::input("./sample.mp4")
+            .output()
+            .resize(-2, 320)
+            .format("matroska")
+            .async_write_to(&mut sink)
+            .await
+            .unwrap();
+        assert_eq!(result.bytes_written, sink.into_inner().len() as u64);
+        assert!(result.status.success());
+    }
+
+    #[cfg(all(feature = "runtime-async-process", not(feature = "runtime-tokio")))]
+    #[test]
+    fn output_to_stream_without_tokio() {
+        use futures::AsyncReadExt;
+        init();
+        let mut reader = FFMpeg::input("./sample.mp4")
+            .output()
+            .resize(-2, 320)
+            .stream()
+            .unwrap();
+        let mut buf = vec![];
+        futures::executor::block_on(reader.read_to_end(&mut buf)).unwrap();
+        assert!(!buf.is_empty());
+        let out = futures::executor::block_on(
+            FFMpeg::input("./sample.mp4")
+                .output()
+                .resize(-2, 320)
+                .async_save("./output/output-async-process.mp4"),
+        );
+        assert!(out.is_ok());
+    }
+
+    #[test]
+    fn output_to_blocking_stream() {
+        init();
+        let mut reader = FFMpeg::input("./sample.mp4")
+            .output()
+            .resize(-2, 320)
+            .blocking_stream()
+            .unwrap();
+        let mut output_file = std::fs::File::create("./output/output-blocking-stream.mp4").unwrap();
+        let size = std::io::copy(&mut reader, &mut output_file).unwrap();
+        assert!(size > 0);
+
+        let mut reader = FFMpeg::input("./not-exists.mp4")
+            .output()
+            .blocking_stream()
+            .unwrap();
+        let mut buf = vec![];
+        assert!(std::io::Read::read_to_end(&mut reader, &mut buf).is_err());
+    }
+
     #[tokio::test]
     async fn resize_to_320p() {
         init();
@@ -384,4 +949,479 @@ mod tests {
         let d = now.elapsed();
         println!("sync save time cost: {d:?}");
     }
+
+    #[test]
+    fn measure_loudness() {
+        init();
+        let loudness = analysis::loudness("./sample.mp4").unwrap();
+        assert!(loudness.integrated < 0.0);
+        assert!(loudness.lra >= 0.0);
+    }
+
+    #[test]
+    fn normalize_loudness() {
+        for target in [-80.0, 0.0] {
+            let err = FFMpeg::input("./sample.mp4")
+                .output()
+                .normalize_loudness(target)
+                .save("./output/normalized.mp4")
+                .unwrap_err();
+            assert_eq!(err.kind, ErrorKind::InvalidOption);
+        }
+
+        init();
+        FFMpeg::input("./sample.mp4")
+            .output()
+            .normalize_loudness(-16.0)
+            .save("./output/normalized.mp4")
+            .unwrap();
+        let loudness = analysis::loudness("./output/normalized.mp4").unwrap();
+        assert!((loudness.integrated + 16.0).abs() < 1.0);
+    }
+
+    #[test]
+    fn detect_crop() {
+        init();
+        let points: Vec<_> = [10, 20, 30]
+            .iter()
+            .map(|s| time::Duration::from_secs(*s))
+            .collect();
+        let crop = analysis::detect_crop("./sample.mp4", &points).unwrap();
+        FFMpeg::input("./sample.mp4")
+            .output()
+            .crop(crop)
+            .resize(-2, 320)
+            .save("./output/cropped_320p.mp4")
+            .unwrap();
+    }
+
+    #[test]
+    fn compare_quality() {
+        init();
+        FFMpeg::input("./sample.mp4")
+            .output()
+            .resize(-2, 320)
+            .save("./output/compare_320p.mp4")
+            .unwrap();
+        let report = analysis::compare(
+            "./sample.mp4",
+            "./output/compare_320p.mp4",
+            &[
+                analysis::Metric::Psnr,
+                analysis::Metric::Ssim,
+                analysis::Metric::Vmaf,
+                analysis::Metric::Psnr,
+            ],
+        )
+        .unwrap();
+        let psnr = report.psnr.unwrap();
+        let ssim = report.ssim.unwrap();
+        assert!(!psnr.frames.is_empty());
+        assert!(ssim.average > 0.0 && ssim.average <= 1.0);
+    }
+
+    #[test]
+    fn sprite_sheet() {
+        init();
+        let output = tools::sprite_sheet("./sample.mp4", &time::Duration::from_secs(5), 4, 4, 160)
+            .save("./output/sprites")
+            .unwrap();
+        assert!(!output.sprites.is_empty());
+        let vtt = fs::read_to_string(output.vtt).unwrap();
+        assert!(vtt.starts_with("WEBVTT"));
+        assert!(vtt.contains("sprite_001.jpg#xywh=0,0,160,"));
+    }
+
+    #[test]
+    fn multiple_screenshots() {
+        let err = tools::screenshots(
+            "./sample.mp4",
+            tools::ScreenshotSpec::Every(time::Duration::ZERO),
+        )
+        .save("./output/thumb_%03d.jpg")
+        .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidOption);
+
+        init();
+        // written at the same moment, but not by this run
+        fs::write("./output/thumb_006.jpg", b"").unwrap();
+        let files = tools::screenshots("./sample.mp4", tools::ScreenshotSpec::Count(5))
+            .resize(-2, 320)
+            .save("./output/thumb_%03d.jpg")
+            .unwrap();
+        assert_eq!(files.len(), 5);
+        assert_eq!(files[4], "./output/thumb_005.jpg");
+
+        let images = tools::screenshots(
+            "./sample.mp4",
+            tools::ScreenshotSpec::Timestamps(vec![
+                time::Duration::from_secs(10),
+                time::Duration::from_millis(20500),
+            ]),
+        )
+        .to_images()
+        .unwrap();
+        assert_eq!(images.len(), 2);
+    }
+
+    #[test]
+    fn animated_previews() {
+        init();
+        let start = time::Duration::from_secs(30);
+        let duration = time::Duration::from_secs(2);
+        for (format, file) in [
+            (tools::AnimatedFormat::Gif, "./output/preview.gif"),
+            (tools::AnimatedFormat::WebP, "./output/preview.webp"),
+            (tools::AnimatedFormat::Apng, "./output/preview.apng"),
+        ] {
+            tools::animated("./sample.mp4", &start, &duration, format)
+                .fps(12)
+                .width(320)
+                .save(file)
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn watermark() {
+        let watermark = tools::watermark(
+            "./sample.mp4",
+            "./output/logo.png",
+            tools::Position::TopLeft,
+            0,
+            1.0,
+            None,
+        );
+        let err = watermark
+            .output()
+            .resize(-2, 320)
+            .build_args(Some("./output/watermarked.mp4".to_owned()))
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidOption);
+
+        init();
+        tools::screenshot("./sample.mp4", &time::Duration::from_secs(10))
+            .resize(200, -2)
+            .save("./output/logo.png")
+            .unwrap();
+        tools::watermark(
+            "./sample.mp4",
+            "./output/logo.png",
+            tools::Position::BottomRight,
+            20,
+            0.7,
+            Some(0.2),
+        )
+        .between(&time::Duration::from_secs(2), &time::Duration::from_secs(10))
+        .output()
+        .timeout(15)
+        .save("./output/watermarked.mp4")
+        .unwrap();
+    }
+
+    #[test]
+    fn draw_text() {
+        let draft = DrawText::text("DRAFT: don't share [v1], 100%")
+            .font_size(48)
+            .position(tools::Position::Center, 0)
+            .between(&time::Duration::from_secs(1), &time::Duration::from_secs(5));
+        assert_eq!(
+            draft.to_filter(),
+            r"drawtext=expansion=none:text=DRAFT\\: don\\\'t share \[v1\]\, 100%:fontsize=48:fontcolor=white:x=(w-tw)/2:y=(h-th)/2:enable=between(t\,1\,5)"
+        );
+
+        init();
+        FFMpeg::input("./sample.mp4")
+            .output()
+            .draw_text(draft)
+            .draw_text(
+                DrawText::timecode("00:00:00:00", 24.0)
+                    .background("black@0.5", 8)
+                    .position(tools::Position::BottomLeft, 20),
+            )
+            .timeout(6)
+            .save("./output/draw_text.mp4")
+            .unwrap();
+    }
+
+    #[test]
+    fn subtitles() {
+        let has = |args: &[String], pair: [&str; 2]| args.windows(2).any(|w| w == pair);
+        let args = FFMpeg::input("./sample.mkv")
+            .output()
+            .add_subtitle(SubtitleTrack::new("./output/sample.srt"))
+            .build_args(Some("./output/soft_subtitles.mkv".to_owned()))
+            .unwrap();
+        for map in ["0:v?", "0:a?", "0:s?", "0:t?", "1:s:0"] {
+            assert!(has(&args, ["-map", map]), "{map} is not mapped");
+        }
+        let args = FFMpeg::input("./sample.mkv")
+            .output()
+            .add_subtitle(SubtitleTrack::new("./output/sample.srt"))
+            .build_args(Some("./output/soft_subtitles.mp4".to_owned()))
+            .unwrap();
+        assert!(has(&args, ["-map", "0:s?"]) && !has(&args, ["-map", "0:t?"]));
+
+        init();
+        fs::write(
+            "./output/sample.srt",
+            "1\n00:00:01,000 --> 00:00:04,000\nHello, world\n\n2\n00:00:05,000 --> 00:00:08,000\nSecond line\n",
+        )
+        .unwrap();
+        subtitle::convert("./output/sample.srt")
+            .save("./output/sample.ass")
+            .unwrap();
+        FFMpeg::input("./sample.mp4")
+            .output()
+            .add_subtitle(
+                SubtitleTrack::new("./output/sample.srt")
+                    .language("eng")
+                    .default(),
+            )
+            .add_subtitle(
+                SubtitleTrack::new("./output/sample.ass")
+                    .language("fre")
+                    .forced(),
+            )
+            .timeout(10)
+            .save("./output/soft_subtitles.mkv")
+            .unwrap();
+        subtitle::extract("./output/soft_subtitles.mkv", 1)
+            .save("./output/extracted.vtt")
+            .unwrap();
+        FFMpeg::input("./sample.mp4")
+            .output()
+            .burn_subtitles("./output/sample.ass")
+            .timeout(10)
+            .save("./output/burnt_subtitles.mp4")
+            .unwrap();
+    }
+
+    #[test]
+    fn metadata_and_chapters() {
+        let err = FFMpeg::input("./sample.mp4")
+            .output()
+            .stream_copy()
+            .resize(-2, 320)
+            .build_args(Some("./output/retagged.mp4".to_owned()))
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidOption);
+
+        init();
+        let chapters = vec![
+            Chapter::new(
+                time::Duration::ZERO,
+                time::Duration::from_secs(20),
+                "Opening",
+            ),
+            Chapter::new(
+                time::Duration::from_secs(20),
+                time::Duration::from_secs(52),
+                "Dragon; fight",
+            ),
+        ];
+        FFMpeg::input("./sample.mp4")
+            .output()
+            .map_metadata(MetadataSource::Strip)
+            .metadata("title", "Sintel trailer")
+            .stream_metadata("a:0", "language", "eng")
+            .chapters(chapters)
+            .save("./output/chapters.mp4")
+            .unwrap();
+
+        tools::screenshot("./sample.mp4", &time::Duration::from_secs(10))
+            .save("./output/cover.jpg")
+            .unwrap();
+        FFMpeg::input("./sample.mp4")
+            .only_audio()
+            .output()
+            .metadata("artist", "Blender Foundation")
+            .cover_art("./output/cover.jpg")
+            .save("./output/with_cover.mp3")
+            .unwrap();
+        FFMpeg::input("./output/with_cover.mp3")
+            .output()
+            .metadata("album", "Sintel")
+            .stream_copy()
+            .save("./output/retagged.mp3")
+            .unwrap();
+    }
+
+    #[test]
+    fn select_streams() {
+        let err = FFMpeg::input("./sample.mp4")
+            .take_stream(1)
+            .output()
+            .build_args(None)
+            .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::StreamSelection);
+
+        let args = FFMpeg::input("./sample.mkv")
+            .select(StreamSelector::stream_type(StreamType::Subtitle).exclude())
+            .output()
+            .build_args(None)
+            .unwrap();
+        assert!(args.join(" ").contains("-map 0 -map -0:s"));
+
+        init();
+        let info = analysis::probe("./sample.mp4").unwrap();
+        assert!(info
+            .streams
+            .iter()
+            .any(|stream| stream.stream_type == StreamType::Video && stream.width.is_some()));
+
+        let args = FFMpeg::input("./sample.mp4")
+            .select(StreamSelector::stream_type(StreamType::Video).index(0))
+            .select(
+                StreamSelector::stream_type(StreamType::Audio)
+                    .language("eng")
+                    .optional(),
+            )
+            .select(StreamSelector::stream_type(StreamType::Subtitle).exclude())
+            .select(StreamSelector::matching(|stream| {
+                stream.stream_type == StreamType::Audio
+            }))
+            .output()
+            .build_args(None)
+            .unwrap();
+        let args = args.join(" ");
+        assert!(args.contains("-map 0:v:0 -map 0:a:m:language:eng? -map -0:s -map 0:1"));
+    }
+
+    #[test]
+    fn audio_tools() {
+        let err = audio::mix(&[]).err().unwrap();
+        assert_eq!(err.kind, ErrorKind::InvalidOption);
+        let err = audio::duck(
+            "./sample.mp4",
+            "./output/music.m4a",
+            &audio::Ducking::default(),
+        )
+        .volume_db(-3.0)
+        .build_args(Some("./output/ducked.m4a".to_owned()))
+        .unwrap_err();
+        assert_eq!(err.kind, ErrorKind::InvalidOption);
+
+        init();
+        FFMpeg::input("./sample.mp4")
+            .output()
+            .args(vec!["-vn"])
+            .save("./output/music.m4a")
+            .unwrap();
+        audio::mix(&[("./sample.mp4", 1.0), ("./output/music.m4a", 0.3)])
+            .unwrap()
+            .save("./output/mixed.m4a")
+            .unwrap();
+        audio::duck(
+            "./sample.mp4",
+            "./output/music.m4a",
+            &audio::Ducking::default(),
+        )
+        .save("./output/ducked.m4a")
+        .unwrap();
+        audio::upmix_to_5_1("./output/music.m4a")
+            .save("./output/upmixed.m4a")
+            .unwrap();
+        audio::downmix_to_stereo("./output/upmixed.m4a")
+            .unwrap()
+            .volume_db(-3.0)
+            .fade_in(2.0)
+            .fade_out(40.0, 5.0)
+            .save("./output/downmixed.m4a")
+            .unwrap();
+        let files = audio::split_channels("./output/music.m4a", "./output/channel_%d.wav").unwrap();
+        assert_eq!(files.len(), 2);
+    }
+
+    #[test]
+    fn speed_reverse_and_loop() {
+        for factor in [0.0, -2.0, f64::NAN] {
+            let err = tools::speed("./sample.mp4", factor).err().unwrap();
+            assert_eq!(err.kind, ErrorKind::InvalidOption);
+        }
+
+        init();
+        tools::speed("./sample.mp4", 4.0)
+            .unwrap()
+            .save("./output/4x.mp4")
+            .unwrap();
+        tools::speed("./sample.mp4", 0.25)
+            .unwrap()
+            .timeout(10)
+            .save("./output/slow_motion.mp4")
+            .unwrap();
+        tools::reverse("./sample.mp4")
+            .chunk_duration(&time::Duration::from_secs(20))
+            .save("./output/reversed.mp4")
+            .unwrap();
+        tools::loop_n("./output/4x.mp4", 3)
+            .save("./output/looped.mp4")
+            .unwrap();
+        let duration = analysis::duration("./output/looped.mp4").unwrap();
+        let once = analysis::duration("./output/4x.mp4").unwrap();
+        assert!(duration > once * 2);
+    }
+
+    #[test]
+    fn concat_with_transitions() {
+        let instant = Transition::new(TransitionKind::Fade, &time::Duration::ZERO);
+        let err = FFMpegMultipleInput::concat_with_transitions(
+            &["./sample.mp4", "./sample1.mp4"],
+            &[instant],
+        )
+        .err()
+        .unwrap();
+        assert_eq!(err.kind, ErrorKind::InvalidOption);
+        assert_eq!(utils::parse_frame_rate("23.98"), Some((24000, 1001)));
+        assert_eq!(utils::parse_frame_rate("29.97"), Some((30000, 1001)));
+        assert_eq!(utils::parse_frame_rate("25"), Some((25, 1)));
+        assert_eq!(utils::parse_frame_rate("12.5"), Some((25, 2)));
+        assert_eq!(utils::parse_frame_rate("1k"), Some((1000, 1)));
+
+        init();
+        let fade = Transition::new(TransitionKind::Fade, &time::Duration::from_secs(1));
+        let wipe = Transition::new(TransitionKind::WipeLeft, &time::Duration::from_millis(500));
+        FFMpegMultipleInput::concat_with_transitions(
+            &["./sample.mp4", "./sample1.mp4", "./sample.mp4"],
+            &[fade, wipe],
+        )
+        .unwrap()
+        .save("./output/transitions.mp4")
+        .unwrap();
+    }
+
+    #[test]
+    fn compose_layouts() {
+        let inputs = ["./sample.mp4", "./sample1.mp4", "./sample.mp4"];
+        for layout in [tools::Layout::SideBySide, tools::Layout::Grid { cols: 2 }] {
+            let err = tools::compose(&inputs[..1], layout).output().err().unwrap();
+            assert_eq!(err.kind, ErrorKind::InvalidOption);
+        }
+        let pip = tools::Layout::PiP {
+            corner: tools::Position::BottomRight,
+            scale: 0.25,
+        };
+        let err = tools::compose(&inputs, pip).output().err().unwrap();
+        assert_eq!(err.kind, ErrorKind::InvalidOption);
+
+        init();
+        tools::compose(&inputs[..2], tools::Layout::SideBySide)
+            .cell_size(640, 360)
+            .duration(tools::DurationMode::Longest)
+            .save("./output/side_by_side.mp4")
+            .unwrap();
+        tools::compose(
+            &inputs[..2],
+            tools::Layout::PiP {
+                corner: tools::Position::BottomRight,
+                scale: 0.25,
+            },
+        )
+        .save("./output/pip.mp4")
+        .unwrap();
+        tools::compose(&inputs, tools::Layout::Grid { cols: 2 })
+            .cell_size(320, 180)
+            .save("./output/grid.mp4")
+            .unwrap();
+    }
 }
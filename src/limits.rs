@@ -0,0 +1,363 @@
+use std::{
+    io::{self, Read},
+    process::{Child, Command, ExitStatus, Output},
+    sync::{mpsc, Arc, Mutex},
+    thread, time,
+};
+
+use crate::error::{Error, ErrorKind};
+
+const POLL_INTERVAL: time::Duration = time::Duration::from_millis(20);
+
+/// lowercase messages of allocations failing, from ffmpeg, libc or the loader
+const OUT_OF_MEMORY_ERRORS: &[&str] = &[
+    "cannot allocate memory",
+    "out of memory",
+    "failed to map segment",
+];
+
+/// io scheduling class of ffmpeg, see `ionice(1)`. levels go from 0 (highest)
+/// to 7 (lowest)
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::{limits::IoPriority, FFMpeg};
+/// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+/// FFMpeg::input("./sample.mp4")
+///     .output()
+///     .threads(2)
+///     .filter_threads(1)
+///     .nice(10)
+///     .io_priority(IoPriority::Idle)
+///     .memory_limit(1 << 30)
+///     .cpu_time_limit(std::time::Duration::from_secs(600))
+///     .wall_timeout(std::time::Duration::from_secs(300))
+///     .save("./output/limited.mp4")
+///     .unwrap();
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IoPriority {
+    /// served before all other io, needs privileges
+    RealTime(u8),
+    BestEffort(u8),
+    /// only served when no other process uses the disk
+    Idle,
+}
+
+impl IoPriority {
+    /// value of `ioprio_set`, class in top bits and level in the low ones
+    #[cfg(target_os = "linux")]
+    fn value(&self) -> libc::c_int {
+        const CLASS_SHIFT: libc::c_int = 13;
+        match *self {
+            IoPriority::RealTime(level) => (1 << CLASS_SHIFT) | level as libc::c_int,
+            IoPriority::BestEffort(level) => (2 << CLASS_SHIFT) | level as libc::c_int,
+            IoPriority::Idle => 3 << CLASS_SHIFT,
+        }
+    }
+
+    fn level(&self) -> u8 {
+        match *self {
+            IoPriority::RealTime(level) | IoPriority::BestEffort(level) => level,
+            IoPriority::Idle => 0,
+        }
+    }
+}
+
+/// limits of the ffmpeg process, as opposed to options passed to ffmpeg
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Limits {
+    pub nice: Option<i32>,
+    pub io_priority: Option<IoPriority>,
+    pub memory: Option<u64>,
+    pub cpu_time: Option<time::Duration>,
+    pub wall_timeout: Option<time::Duration>,
+}
+
+impl Limits {
+    /// make `command` set niceness, io priority and rlimits in the child,
+    /// before ffmpeg is executed
+    pub fn apply(&self, command: &mut Command) -> Result<(), Error> {
+        if let Some(nice) = self.nice {
+            if !(-20..=19).contains(&nice) {
+                return Err(invalid(format!("nice must be in -20..=19, got {nice}")));
+            }
+        }
+        if let Some(priority) = self.io_priority {
+            if priority.level() > 7 {
+                return Err(invalid(format!(
+                    "io priority level must be in 0..=7, got {}",
+                    priority.level()
+                )));
+            }
+            if !cfg!(target_os = "linux") {
+                return Err(invalid("io priority is only supported on linux".to_owned()));
+            }
+        }
+        if self.nice.is_none()
+            && self.io_priority.is_none()
+            && self.memory.is_none()
+            && self.cpu_time.is_none()
+        {
+            return Ok(());
+        }
+        self.apply_in_child(command)
+    }
+
+    #[cfg(unix)]
+    fn apply_in_child(&self, command: &mut Command) -> Result<(), Error> {
+        use std::os::unix::process::CommandExt;
+
+        let Limits {
+            nice,
+            io_priority,
+            memory,
+            cpu_time,
+            ..
+        } = self.clone();
+        let pre_exec = move || {
+            // runs between fork and exec, only async signal safe calls here
+            if let Some(nice) = nice {
+                if unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) } == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            #[cfg(target_os = "linux")]
+            if let Some(priority) = io_priority {
+                const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+                let set = unsafe {
+                    libc::syscall(
+                        libc::SYS_ioprio_set,
+                        IOPRIO_WHO_PROCESS,
+                        0,
+                        priority.value(),
+                    )
+                };
+                if set == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            #[cfg(not(target_os = "linux"))]
+            let _ = io_priority;
+            if let Some(memory) = memory {
+                set_rlimit(libc::RLIMIT_AS, memory)?;
+            }
+            if let Some(cpu_time) = cpu_time {
+                // SIGXCPU at the soft limit lets ffmpeg stop itself
+                set_rlimit(libc::RLIMIT_CPU, cpu_time.as_secs().max(1))?;
+            }
+            Ok(())
+        };
+        unsafe {
+            command.pre_exec(pre_exec);
+        }
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn apply_in_child(&self, _command: &mut Command) -> Result<(), Error> {
+        Err(invalid(
+            "nice and memory/cpu limits are only supported on unix".to_owned(),
+        ))
+    }
+
+    /// error of a ffmpeg run that ended with `status` because a limit was hit,
+    /// `None` if it ended for any other reason
+    pub fn exceeded(&self, status: &ExitStatus, stderr: &str, timed_out: bool) -> Option<Error> {
+        if timed_out {
+            let timeout = self.wall_timeout.unwrap_or_default();
+            return Some(Error {
+                kind: ErrorKind::Timeout,
+                msg: format!("ffmpeg was killed after running longer than {timeout:?}"),
+            });
+        }
+        if status.success() {
+            return None;
+        }
+        let signal = signal_of(status);
+        if let Some(cpu_time) = self.cpu_time {
+            // SIGKILL or exit code 255 may come from anything else, e.g. the
+            // oom killer or a user interrupt, only SIGXCPU is the cpu limit
+            if signal == Some(SIGXCPU) {
+                return Some(limit_error(
+                    format!("cpu time limit of {cpu_time:?}"),
+                    stderr,
+                ));
+            }
+        }
+        if let Some(memory) = self.memory {
+            let stderr_lower = stderr.to_lowercase();
+            let out_of_memory = OUT_OF_MEMORY_ERRORS
+                .iter()
+                .any(|pattern| stderr_lower.contains(pattern));
+            // allocations failing where ffmpeg does not check them crash it
+            if out_of_memory || signal.is_some_and(|signal| CRASH_SIGNALS.contains(&signal)) {
+                return Some(limit_error(
+                    format!("memory limit of {memory} bytes"),
+                    stderr,
+                ));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(unix)]
+fn set_rlimit(resource: RlimitResource, value: u64) -> io::Result<()> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(resource, &mut limit) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // hard limit is left alone, raising it needs privileges
+    limit.rlim_cur = (value as libc::rlim_t).min(limit.rlim_max);
+    if unsafe { libc::setrlimit(resource, &limit) } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type RlimitResource = libc::__rlimit_resource_t;
+#[cfg(all(unix, not(all(target_os = "linux", target_env = "gnu"))))]
+type RlimitResource = libc::c_int;
+
+#[cfg(unix)]
+const SIGXCPU: i32 = libc::SIGXCPU;
+#[cfg(unix)]
+const CRASH_SIGNALS: [i32; 3] = [libc::SIGSEGV, libc::SIGABRT, libc::SIGBUS];
+#[cfg(not(unix))]
+const SIGXCPU: i32 = -1;
+#[cfg(not(unix))]
+const CRASH_SIGNALS: [i32; 0] = [];
+
+#[cfg(unix)]
+fn signal_of(status: &ExitStatus) -> Option<i32> {
+    std::os::unix::process::ExitStatusExt::signal(status)
+}
+
+#[cfg(not(unix))]
+fn signal_of(_status: &ExitStatus) -> Option<i32> {
+    None
+}
+
+fn invalid(msg: String) -> Error {
+    Error {
+        kind: ErrorKind::InvalidOption,
+        msg,
+    }
+}
+
+fn limit_error(limit: String, stderr: &str) -> Error {
+    let mut msg = format!("ffmpeg exceeded {limit}");
+    if !stderr.trim().is_empty() {
+        msg.push_str(": ");
+        msg.push_str(stderr.trim());
+    }
+    Error {
+        kind: ErrorKind::ResourceLimit,
+        msg,
+    }
+}
+
+/// wait for `child`, killing it once `timeout` has passed. returns whether it
+/// was killed
+pub(crate) fn wait_timeout(
+    child: &mut Child,
+    timeout: Option<time::Duration>,
+) -> io::Result<(ExitStatus, bool)> {
+    let Some(timeout) = timeout else {
+        return Ok((child.wait()?, false));
+    };
+    let deadline = time::Instant::now() + timeout;
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok((status, false));
+        }
+        if time::Instant::now() >= deadline {
+            let _ = child.kill();
+            return Ok((child.wait()?, true));
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// same as `Child::wait_with_output`, killing child once `timeout` has passed.
+/// returns whether it was killed
+pub(crate) fn wait_with_output(
+    mut child: Child,
+    timeout: Option<time::Duration>,
+) -> io::Result<(Output, bool)> {
+    let stdout = child.stdout.take();
+    let stderr = child.stderr.take();
+    // both pipes are drained while waiting, ffmpeg would block on a full one
+    let (waited, stdout, stderr) = thread::scope(|scope| {
+        let stdout = scope.spawn(|| read_pipe(stdout));
+        let stderr = scope.spawn(|| read_pipe(stderr));
+        let waited = wait_timeout(&mut child, timeout);
+        (waited, stdout.join().unwrap(), stderr.join().unwrap())
+    });
+    let (status, timed_out) = waited?;
+    Ok((
+        Output {
+            status,
+            stdout: stdout?,
+            stderr: stderr?,
+        },
+        timed_out,
+    ))
+}
+
+fn read_pipe(pipe: Option<impl Read>) -> io::Result<Vec<u8>> {
+    let mut buf = vec![];
+    if let Some(mut pipe) = pipe {
+        pipe.read_to_end(&mut buf)?;
+    }
+    Ok(buf)
+}
+
+/// kills a child shared with its reader once timeout has passed, for readers
+/// that block on output and can not check the time themselves
+pub(crate) struct Watchdog {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<thread::JoinHandle<bool>>,
+}
+
+impl Watchdog {
+    pub fn start(child: Arc<Mutex<Child>>, timeout: time::Duration) -> Self {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = thread::spawn(move || match stopped.recv_timeout(timeout) {
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                // a child that exited in time but was not reaped yet is left
+                // alone, so that its own status is reported and not a timeout
+                let mut child = child.lock().unwrap();
+                match child.try_wait() {
+                    Ok(None) => child.kill().is_ok(),
+                    _ => false,
+                }
+            }
+            _ => false,
+        });
+        Watchdog {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    /// stop watching, returns whether child was killed while still running
+    pub fn stop(&mut self) -> bool {
+        self.stop.take();
+        self.thread
+            .take()
+            .map(|thread| thread.join().unwrap_or(false))
+            .unwrap_or(false)
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
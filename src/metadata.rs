@@ -0,0 +1,51 @@
+use std::time;
+
+/// chapter written to output through an FFMETADATA file
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chapter {
+    pub start: time::Duration,
+    pub end: time::Duration,
+    pub title: String,
+}
+
+impl Chapter {
+    pub fn new(start: time::Duration, end: time::Duration, title: &str) -> Self {
+        Self {
+            start,
+            end,
+            title: title.to_owned(),
+        }
+    }
+}
+
+/// where global metadata of output comes from
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MetadataSource {
+    /// copy metadata of input at given index, ffmpeg copies from the first input by default
+    Input(usize),
+    /// drop metadata of all inputs
+    Strip,
+}
+
+/// render chapters in FFMETADATA1 format
+pub(crate) fn to_ffmetadata(chapters: &[Chapter]) -> String {
+    let mut s = String::from(";FFMETADATA1\n");
+    for chapter in chapters {
+        s.push_str("[CHAPTER]\nTIMEBASE=1/1000\n");
+        s.push_str(&format!("START={}\n", chapter.start.as_millis()));
+        s.push_str(&format!("END={}\n", chapter.end.as_millis()));
+        s.push_str(&format!("title={}\n", escape(&chapter.title)));
+    }
+    s
+}
+
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
@@ -1,23 +1,51 @@
-use std::{cmp, io::Write, path::PathBuf, pin::Pin, process::Stdio, str::FromStr, task::Poll};
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-process"))]
+use std::{
+    cmp,
+    pin::Pin,
+    task::{ready, Context, Poll},
+};
+use std::{
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdout, Command, ExitStatus, Stdio},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    thread, time,
+};
 
 use crate::{
-    error::Error,
-    input::{FFMpegMultipleInput, MergeStrategy, StreamType},
+    analysis::{self, Crop},
+    container::{self, StreamingContainer},
+    drawtext::DrawText,
+    error::{Error, ErrorKind},
+    input::{FFMpegMultipleInput, MergeStrategy},
+    limits::{self, IoPriority, Limits, Watchdog},
+    metadata::{self, Chapter, MetadataSource},
     owned,
-    utils::read_to_string,
+    subtitle::{self, SubtitleTrack},
+    utils::{escape_filter_value, parse_time},
+    verify::{self, Expected, Verification, VerificationReport},
     FFMpeg,
 };
 
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-process"))]
+use crate::runtime;
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-process"))]
+use bytes::{Bytes, BytesMut};
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-process"))]
+use futures::{
+    channel::mpsc::{self, Receiver, Sender},
+    AsyncReadExt, AsyncWriteExt, SinkExt, Stream, StreamExt,
+};
 use tempfile::NamedTempFile;
-use tokio::{io::AsyncRead, sync::mpsc::Receiver};
-#[cfg(feature = "async")]
-use tokio::{io::AsyncReadExt, process};
 
+#[derive(Clone)]
 pub struct FFmpegOutput {
     output_option: OutputOption,
     pub(crate) inputs: FFMpegMultipleInput,
 }
 
+#[derive(Clone)]
 struct OutputOption {
     stream_buffer_size: usize,
     size: Option<(i32, i32)>,
@@ -27,16 +55,66 @@ struct OutputOption {
     custom_args: Vec<String>,
     video_filters: Vec<String>,
     audio_filters: Vec<String>,
-    temp_input_filelist: Option<NamedTempFile>,
+    temp_input_filelist: Option<Arc<NamedTempFile>>,
     timeout: Option<u64>,
     verbose: bool,
+    loudness_target: Option<f64>,
+    loudnorm: Option<String>,
+    crop: Option<Crop>,
+    filter_complex: Vec<String>,
+    subtitles: Vec<SubtitleTrack>,
+    subtitle_codec: Option<String>,
+    metadata: Vec<(String, String)>,
+    stream_metadata: Vec<(String, String, String)>,
+    metadata_source: Option<MetadataSource>,
+    chapters: Vec<Chapter>,
+    temp_ffmetadata: Option<Arc<NamedTempFile>>,
+    cover_art: Option<String>,
+    stream_copy: bool,
+    streaming_container: Option<StreamingContainer>,
+    /// `None` is `Overwrite`, except that verbose runs leave it to ffmpeg
+    overwrite: Option<OverwritePolicy>,
+    in_place: bool,
+    threads: Option<usize>,
+    filter_threads: Option<usize>,
+    limits: Limits,
 }
 
+#[derive(Debug)]
 pub struct SpawnResult {
     pub stdout: String,
     pub stderr: String,
 }
 
+/// what to do when output file already exists
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    /// replace existing file, `-y`
+    #[default]
+    Overwrite,
+    /// fail without touching existing file, `-n`
+    Fail,
+}
+
+impl OverwritePolicy {
+    fn flag(&self) -> &'static str {
+        match self {
+            OverwritePolicy::Overwrite => "-y",
+            OverwritePolicy::Fail => "-n",
+        }
+    }
+}
+
+/// result of writing ffmpeg output into a sink with `write_to`
+#[derive(Debug, Clone)]
+pub struct WriteResult {
+    pub bytes_written: u64,
+    /// exit status of ffmpeg
+    pub status: ExitStatus,
+    /// what ffmpeg wrote to stderr, warnings of a successful run
+    pub stderr: String,
+}
+
 impl FFmpegOutput {
     pub fn new(ffmpeg_input: FFMpegMultipleInput) -> Self {
         FFmpegOutput {
@@ -52,6 +130,25 @@ impl FFmpegOutput {
                 temp_input_filelist: None,
                 timeout: None,
                 verbose: false,
+                loudness_target: None,
+                loudnorm: None,
+                crop: None,
+                filter_complex: vec![],
+                subtitles: vec![],
+                subtitle_codec: None,
+                metadata: vec![],
+                stream_metadata: vec![],
+                metadata_source: None,
+                chapters: vec![],
+                temp_ffmetadata: None,
+                cover_art: None,
+                stream_copy: false,
+                streaming_container: None,
+                overwrite: None,
+                in_place: false,
+                threads: None,
+                filter_threads: None,
+                limits: Limits::default(),
             },
             inputs: ffmpeg_input,
         }
@@ -65,79 +162,555 @@ impl FFmpegOutput {
         self
     }
 
+    /// limit duration of output to `timeout` seconds, `-t`. see `wall_timeout`
+    /// to limit how long ffmpeg runs
     pub fn timeout(mut self, timeout: u64) -> Self {
         self.output_option.timeout = Some(timeout);
         self
     }
 
+    /// threads of encoders, `-threads`
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.output_option.threads = Some(threads);
+        self
+    }
+
+    /// threads of each simple filter graph, `-filter_threads`
+    pub fn filter_threads(mut self, threads: usize) -> Self {
+        self.output_option.filter_threads = Some(threads);
+        self
+    }
+
+    /// niceness of ffmpeg, from -20 (highest priority) to 19 (lowest). going
+    /// below niceness of current process needs privileges. unix only
+    pub fn nice(mut self, nice: i32) -> Self {
+        self.output_option.limits.nice = Some(nice);
+        self
+    }
+
+    /// io scheduling class of ffmpeg, linux only
+    pub fn io_priority(mut self, priority: IoPriority) -> Self {
+        self.output_option.limits.io_priority = Some(priority);
+        self
+    }
+
+    /// cap address space of ffmpeg to `bytes` (`RLIMIT_AS`), hitting it fails
+    /// with `ErrorKind::ResourceLimit`. unix only
+    pub fn memory_limit(mut self, bytes: u64) -> Self {
+        self.output_option.limits.memory = Some(bytes);
+        self
+    }
+
+    /// cap cpu time of ffmpeg, summed over its threads (`RLIMIT_CPU`). ffmpeg
+    /// killed by `SIGXCPU` fails with `ErrorKind::ResourceLimit`, one that
+    /// catches it exits as on any other signal. unix only
+    pub fn cpu_time_limit(mut self, cpu_time: time::Duration) -> Self {
+        self.output_option.limits.cpu_time = Some(cpu_time);
+        self
+    }
+
+    /// kill ffmpeg once it has run for `timeout`, which fails with
+    /// `ErrorKind::Timeout`. unlike `timeout`, output is not cut short
+    pub fn wall_timeout(mut self, timeout: time::Duration) -> Self {
+        self.output_option.limits.wall_timeout = Some(timeout);
+        self
+    }
+
+    pub(crate) fn limits(&self) -> &Limits {
+        &self.output_option.limits
+    }
+
+    pub(crate) fn has_threads(&self) -> bool {
+        self.output_option.threads.is_some()
+    }
+
+    /// command running ffmpeg with resource limits of this output applied
+    fn command(&self, ffmpeg_bin: &str) -> Result<Command, Error> {
+        let mut command = Command::new(ffmpeg_bin);
+        command.stdin(Stdio::null());
+        self.output_option.limits.apply(&mut command)?;
+        Ok(command)
+    }
+
+    /// save output to `file`, which is first written to a temp file next to it
+    /// and only renamed to `file` once ffmpeg succeeded, see `in_place`
     pub fn save(&mut self, file: &str) -> Result<SpawnResult, Error> {
+        self.measure_loudness()?;
         let ffmpeg_bin = FFMpeg::get_ffmpeg_bin();
 
-        let args = self.build_args(Some(file.to_owned()))?;
-        println!("exec: {} {}", ffmpeg_bin, args.join(" "));
-        let mut child = std::process::Command::new(ffmpeg_bin)
+        let (args, temp) = self.prepare_output(file)?;
+        if self.output_option.verbose {
+            eprintln!("exec: {} {}", ffmpeg_bin, args.join(" "));
+        }
+        let child = self
+            .command(&ffmpeg_bin)?
             .args(args)
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
-            .spawn()
-            .unwrap();
-
-        child.wait().unwrap();
-        let mut stdout = child.stdout.unwrap();
-        let mut stderr = child.stderr.unwrap();
+            .spawn()?;
+        let limits = &self.output_option.limits;
+        let (output, timed_out) = limits::wait_with_output(child, limits.wall_timeout)?;
 
-        let stdout = read_to_string(&mut stdout);
-        let stderr = read_to_string(&mut stderr);
-        if !stderr.is_empty() {
-            return Err(Error { msg: stderr });
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        if let Some(e) = limits.exceeded(&output.status, &stderr, timed_out) {
+            return Err(e);
         }
+        if !stderr.is_empty() || !output.status.success() {
+            return Err(Error {
+                kind: ErrorKind::Ffmpeg,
+                msg: stderr,
+            });
+        }
+        self.persist_output(temp, file)?;
         Ok(SpawnResult { stderr, stdout })
     }
-    #[cfg(feature = "async")]
+    #[cfg(any(feature = "runtime-tokio", feature = "runtime-async-process"))]
     pub async fn async_save(&mut self, file: &str) -> Result<String, Error> {
+        self.async_measure_loudness().await?;
+        let ffmpeg_bin = FFMpeg::get_ffmpeg_bin();
+
+        let (args, temp) = self.prepare_output(file)?;
+        let mut command = self.command(&ffmpeg_bin)?;
+        command.args(args);
+        let mut child = runtime::spawn(command)?;
+        let mut stdout = child.stdout.take().unwrap();
+        let mut stderr = child.stderr.take().unwrap();
+
+        let mut out = String::new();
+        let mut err = String::new();
+        let limits = &self.output_option.limits;
+        let run = async {
+            // both pipes are read together, ffmpeg would block on a full one otherwise
+            let (out_res, err_res) = futures::join!(
+                stdout.read_to_string(&mut out),
+                stderr.read_to_string(&mut err)
+            );
+            out_res?;
+            err_res?;
+            child.wait().await
+        };
+        let (status, timed_out) = match runtime::timeout(limits.wall_timeout, run).await {
+            Some(status) => (status?, false),
+            None => {
+                let _ = child.kill().await;
+                (child.wait().await?, true)
+            }
+        };
+        if let Some(e) = limits.exceeded(&status, &err, timed_out) {
+            return Err(e);
+        }
+        if !err.is_empty() || !status.success() {
+            return Err(Error {
+                kind: ErrorKind::Ffmpeg,
+                msg: err,
+            });
+        }
+        self.persist_output(temp, file)?;
+        Ok(out)
+    }
+
+    /// check `file` saved from this output, see `Verification`
+    pub fn verify(
+        &self,
+        file: &str,
+        verification: &Verification,
+    ) -> Result<VerificationReport, Error> {
+        let expected = Expected {
+            duration: self.expected_duration()?,
+            size: self.output_option.size,
+            video_encoder: self.output_arg(&["-c:v", "-codec:v", "-vcodec"]),
+            audio_encoder: self.output_arg(&["-c:a", "-codec:a", "-acodec"]),
+        };
+        verify::verify(file, expected, verification)
+    }
+
+    /// duration of output worked out from inputs, their trim window and `-t`
+    fn expected_duration(&self) -> Result<Option<time::Duration>, Error> {
+        let mut durations = vec![];
+        for input in &self.inputs.inputs {
+            let Some(ref file) = input.input_file else {
+                return Ok(None);
+            };
+            let start = input.start_time.as_deref().and_then(parse_time);
+            let end = input.end_time.as_deref().and_then(parse_time);
+            let mut duration = analysis::duration(file)?;
+            if let Some(end) = end {
+                duration = duration.min(end);
+            }
+            durations.push(duration.saturating_sub(start.unwrap_or_default()));
+        }
+        let duration = match self.inputs.merge_strategy {
+            MergeStrategy::Concat => durations.into_iter().sum(),
+            MergeStrategy::Merge => durations.into_iter().max().unwrap_or_default(),
+        };
+        Ok(Some(match self.output_option.timeout {
+            Some(timeout) => duration.min(time::Duration::from_secs(timeout)),
+            None => duration,
+        }))
+    }
+
+    /// value following the last of `names` in custom args, e.g. `-c:v`
+    fn output_arg(&self, names: &[&str]) -> Option<String> {
+        let args = &self.output_option.custom_args;
+        let pos = args.iter().rposition(|arg| names.contains(&arg.as_str()))?;
+        args.get(pos + 1).cloned()
+    }
+
+    /// args of ffmpeg saving to `file`, with the temp file it actually writes
+    /// to when `file` can be replaced atomically
+    fn prepare_output(
+        &mut self,
+        file: &str,
+    ) -> Result<(Vec<String>, Option<NamedTempFile>), Error> {
+        if self.output_option.in_place || !self.is_atomic_target(file) {
+            return Ok((self.build_args(Some(file.to_owned()))?, None));
+        }
+        let path = Path::new(file);
+        if self.output_option.overwrite == Some(OverwritePolicy::Fail) && path.exists() {
+            return Err(already_exists(file));
+        }
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let prefix = format!(
+            ".{}.",
+            path.file_stem().unwrap_or_default().to_string_lossy()
+        );
+        // same extension, so that muxer is still inferred from it
+        let suffix = path
+            .extension()
+            .map(|ext| format!(".{}", ext.to_string_lossy()))
+            .unwrap_or_default();
+        let mut builder = tempfile::Builder::new();
+        builder.prefix(&prefix).suffix(&suffix);
+        // temp files are private by default, outputs get usual permissions
+        #[cfg(unix)]
+        builder.permissions(std::os::unix::fs::PermissionsExt::from_mode(0o666));
+        let temp = builder.tempfile_in(dir)?;
+
+        // temp file belongs to us, ffmpeg may always overwrite it
+        let overwrite = self
+            .output_option
+            .overwrite
+            .replace(OverwritePolicy::Overwrite);
+        let args = self.build_args(Some(temp.path().to_string_lossy().into_owned()));
+        self.output_option.overwrite = overwrite;
+        Ok((args?, Some(temp)))
+    }
+
+    /// rename temp file of `prepare_output` to `file`
+    pub(crate) fn persist_output(
+        &self,
+        temp: Option<NamedTempFile>,
+        file: &str,
+    ) -> Result<(), Error> {
+        let Some(temp) = temp else {
+            return Ok(());
+        };
+        let persisted = match self.output_option.overwrite.unwrap_or_default() {
+            OverwritePolicy::Overwrite => temp.persist(file),
+            OverwritePolicy::Fail => temp.persist_noclobber(file),
+        };
+        persisted.map_err(|e| match e.error.kind() {
+            std::io::ErrorKind::AlreadyExists => already_exists(file),
+            _ => e.error.into(),
+        })?;
+        Ok(())
+    }
+
+    /// outputs that are a single regular file, unlike pipes, urls, image
+    /// sequences or segmented formats writing files next to playlist
+    fn is_atomic_target(&self, file: &str) -> bool {
+        let segmented = matches!(
+            self.output_option.format.as_deref(),
+            Some("hls" | "dash" | "segment" | "stream_segment" | "image2")
+        );
+        let ext = Path::new(file)
+            .extension()
+            .map(|ext| ext.to_string_lossy().to_lowercase());
+        !segmented
+            && !matches!(ext.as_deref(), Some("m3u8" | "mpd"))
+            && file != "-"
+            && !file.starts_with("pipe:")
+            && !file.starts_with("/dev/")
+            && !file.contains("://")
+            && !file.contains('%')
+    }
+
+    /// start ffmpeg writing to `file` without waiting for it, stderr is piped
+    /// and `global_args` go before all other args. as with `save`, output goes
+    /// to the returned temp file, which `persist_output` renames to `file`
+    pub(crate) fn spawn(
+        &mut self,
+        file: &str,
+        mut global_args: Vec<String>,
+    ) -> Result<(std::process::Child, Option<NamedTempFile>), Error> {
+        self.measure_loudness()?;
         let ffmpeg_bin = FFMpeg::get_ffmpeg_bin();
+        let (mut args, temp) = self.prepare_output(file)?;
+        global_args.append(&mut args);
+        let child = self
+            .command(&ffmpeg_bin)?
+            .args(global_args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        Ok((child, temp))
+    }
+
+    /// run ffmpeg to `file` and collect its output, unlike `save`, messages on
+    /// stderr are only treated as error when ffmpeg exits with failure
+    pub(crate) fn run(&mut self, file: &str) -> Result<SpawnResult, Error> {
+        let output = self.run_raw(file)?;
+        let stdout = String::from_utf8_lossy(&output.stdout).into_owned();
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        Ok(SpawnResult { stdout, stderr })
+    }
+
+    /// async version of `run`, ffmpeg is spawned on the runtime
+    #[cfg(any(feature = "runtime-tokio", feature = "runtime-async-process"))]
+    pub(crate) async fn async_run(&mut self, file: &str) -> Result<SpawnResult, Error> {
+        let ffmpeg_bin = FFMpeg::get_ffmpeg_bin();
+        let args = self.build_args(Some(file.to_owned()))?;
+        let mut command = self.command(&ffmpeg_bin)?;
+        command.args(args);
+        let mut child = runtime::spawn(command)?;
+        let mut stdout = child.stdout.take().unwrap();
+        let mut stderr = child.stderr.take().unwrap();
+
+        let mut out = String::new();
+        let mut err = String::new();
+        let limits = &self.output_option.limits;
+        let run = async {
+            let (out_res, err_res) = futures::join!(
+                stdout.read_to_string(&mut out),
+                stderr.read_to_string(&mut err)
+            );
+            out_res?;
+            err_res?;
+            child.wait().await
+        };
+        let (status, timed_out) = match runtime::timeout(limits.wall_timeout, run).await {
+            Some(status) => (status?, false),
+            None => {
+                let _ = child.kill().await;
+                (child.wait().await?, true)
+            }
+        };
+        if let Some(e) = limits.exceeded(&status, &err, timed_out) {
+            return Err(e);
+        }
+        if !status.success() {
+            return Err(Error {
+                kind: ErrorKind::Ffmpeg,
+                msg: err,
+            });
+        }
+        Ok(SpawnResult {
+            stdout: out,
+            stderr: err,
+        })
+    }
 
+    /// same as `run` but keeps stdout as bytes, for binary output to `pipe:1`
+    pub(crate) fn run_raw(&mut self, file: &str) -> Result<std::process::Output, Error> {
+        let ffmpeg_bin = FFMpeg::get_ffmpeg_bin();
         let args = self.build_args(Some(file.to_owned()))?;
-        println!("{ffmpeg_bin} {args:?}");
-        let child = process::Command::new(ffmpeg_bin)
+        let child = self
+            .command(&ffmpeg_bin)?
             .args(args)
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .spawn()?;
+        let limits = &self.output_option.limits;
+        let (output, timed_out) = limits::wait_with_output(child, limits.wall_timeout)?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if let Some(e) = limits.exceeded(&output.status, &stderr, timed_out) {
+            return Err(e);
+        }
+        if !output.status.success() {
+            return Err(Error {
+                kind: ErrorKind::Ffmpeg,
+                msg: String::from_utf8_lossy(&output.stderr).into_owned(),
+            });
+        }
+        Ok(output)
+    }
 
-        let mut err_buf = String::with_capacity(100);
-        let mut out_buf = String::with_capacity(100);
+    /// normalize integrated loudness of audio to `target` LUFS (EBU R128)
+    ///
+    /// a measurement pass is run right before the output is produced, then the
+    /// measured values are passed to `loudnorm` to apply a linear gain,
+    /// true peak and loudness range targets are -1.5 dBTP and 11 LU. `target`
+    /// must be in -70..=-5 as `loudnorm` requires
+    ///
+    /// samples:
+    /// ```
+    /// # use ffmpeg_cli_utils::FFMpeg;
+    /// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+    /// FFMpeg::input("./sample.mp4")
+    ///     .output()
+    ///     .normalize_loudness(-16.0)
+    ///     .save("./output/normalized.mp4")
+    ///     .unwrap();
+    /// ```
+    pub fn normalize_loudness(mut self, target: f64) -> Self {
+        self.output_option.loudness_target = Some(target);
+        self.output_option.loudnorm = None;
+        self
+    }
 
-        let mut stdout = child.stdout.unwrap();
-        let mut stderr = child.stderr.unwrap();
+    fn measure_loudness(&mut self) -> Result<(), Error> {
+        let Some((measurement, target)) = self.loudness_measurement()? else {
+            return Ok(());
+        };
+        let measured = analysis::measure_loudness(measurement, target)?;
+        self.set_loudnorm(target, &measured);
+        Ok(())
+    }
 
-        let out = tokio::spawn(async move {
-            let mut out = String::new();
-            while let Ok(size) = stdout.read_to_string(&mut out_buf).await {
-                if size == 0 {
-                    break;
-                }
-                out.push_str(&out_buf[0..size]);
-            }
-            out
-        });
-        let err = tokio::spawn(async move {
-            let mut err = String::new();
-            while let Ok(size) = stderr.read_to_string(&mut err_buf).await {
-                if size == 0 {
-                    break;
-                }
-                err.push_str(&err_buf[0..size]);
-            }
-            err
-        });
+    /// same as `measure_loudness`, with measurement pass spawned on the runtime
+    #[cfg(any(feature = "runtime-tokio", feature = "runtime-async-process"))]
+    async fn async_measure_loudness(&mut self) -> Result<(), Error> {
+        let Some((measurement, target)) = self.loudness_measurement()? else {
+            return Ok(());
+        };
+        let measured = analysis::async_measure_loudness(measurement, target).await?;
+        self.set_loudnorm(target, &measured);
+        Ok(())
+    }
 
-        let err = err.await.unwrap();
-        let out = out.await.unwrap();
-        if !err.is_empty() {
-            return Err(Error { msg: err });
+    /// output of the measurement pass and its target, `None` when loudness is
+    /// not normalized or already measured
+    fn loudness_measurement(&self) -> Result<Option<(FFmpegOutput, f64)>, Error> {
+        let target = match self.output_option.loudness_target {
+            Some(target) if self.output_option.loudnorm.is_none() => target,
+            _ => return Ok(None),
+        };
+        // range accepted by `loudnorm` for I
+        if !(-70.0..=-5.0).contains(&target) {
+            return Err(Error {
+                kind: ErrorKind::InvalidOption,
+                msg: format!("loudness target must be in -70..=-5 LUFS, got {target}"),
+            });
         }
-        Ok(out)
+        let mut measurement = FFmpegOutput::new(self.inputs.clone());
+        measurement.output_option.audio_filters = self.output_option.audio_filters.clone();
+        measurement.output_option.timeout = self.output_option.timeout;
+        measurement.output_option.limits = self.output_option.limits.clone();
+        Ok(Some((measurement, target)))
+    }
+
+    fn set_loudnorm(&mut self, target: f64, measured: &analysis::Loudness) {
+        self.output_option.loudnorm = Some(format!(
+            "loudnorm=I={target}:TP={}:LRA={}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true",
+            analysis::DEFAULT_TRUE_PEAK,
+            analysis::DEFAULT_LRA,
+            measured.integrated,
+            measured.true_peak,
+            measured.lra,
+            measured.threshold,
+            measured.target_offset,
+        ));
+    }
+
+    /// add external subtitle file (srt, ass, vtt...) as a soft subtitle track,
+    /// its codec is picked by extension of output file unless `subtitle_codec` is set.
+    /// subtitles of inputs are kept, along with attachments in matroska
+    pub fn add_subtitle(mut self, track: SubtitleTrack) -> Self {
+        self.output_option.subtitles.push(track);
+        self
+    }
+
+    /// set subtitle encoder, e.g. `mov_text`, `srt`, `ass` or `webvtt`
+    pub fn subtitle_codec(mut self, codec: &str) -> Self {
+        self.output_option.subtitle_codec = Some(codec.to_owned());
+        self
+    }
+
+    /// render subtitle file into video, ass/ssa files keep their styles
+    pub fn burn_subtitles(mut self, file: &str) -> Self {
+        let is_ass = file.to_lowercase().ends_with(".ass") || file.to_lowercase().ends_with(".ssa");
+        let filter = if is_ass { "ass" } else { "subtitles" };
+        self.output_option
+            .video_filters
+            .push(format!("{filter}=filename={}", escape_filter_value(file)));
+        self
+    }
+
+    /// render embedded subtitle stream of `file`, usually the input itself, into video
+    pub fn burn_embedded_subtitles(mut self, file: &str, stream_index: u64) -> Self {
+        self.output_option.video_filters.push(format!(
+            "subtitles=filename={}:si={stream_index}",
+            escape_filter_value(file)
+        ));
+        self
+    }
+
+    /// set global metadata of output, e.g. `title`, `artist` or `album`
+    pub fn metadata(mut self, key: &str, value: &str) -> Self {
+        self.output_option
+            .metadata
+            .push((key.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// set metadata of output streams matching `stream` specifier, e.g. `a:0`
+    pub fn stream_metadata(mut self, stream: &str, key: &str, value: &str) -> Self {
+        self.output_option.stream_metadata.push((
+            stream.to_owned(),
+            key.to_owned(),
+            value.to_owned(),
+        ));
+        self
+    }
+
+    /// choose where global metadata is copied from, values set by `metadata`
+    /// are applied on top of it
+    pub fn map_metadata(mut self, source: MetadataSource) -> Self {
+        self.output_option.metadata_source = Some(source);
+        self
+    }
+
+    /// write chapters to output, replacing chapters of inputs
+    pub fn chapters(mut self, chapters: Vec<Chapter>) -> Self {
+        self.output_option.chapters = chapters;
+        self
+    }
+
+    /// embed image as cover art (attached picture) of an audio output, video
+    /// and subtitle streams of inputs are not mapped when cover art is set
+    pub fn cover_art(mut self, image: &str) -> Self {
+        self.output_option.cover_art = Some(image.to_owned());
+        self
+    }
+
+    /// copy all streams without re-encoding, so only metadata, chapters and
+    /// cover art are changed, filters can not be used together with it and
+    /// fail with `ErrorKind::InvalidOption`
+    ///
+    /// samples:
+    /// ```
+    /// # use ffmpeg_cli_utils::FFMpeg;
+    /// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+    /// FFMpeg::input("./sample.mp4")
+    ///     .output()
+    ///     .metadata("title", "Sintel")
+    ///     .stream_copy()
+    ///     .save("./output/retitled.mp4")
+    ///     .unwrap();
+    /// ```
+    pub fn stream_copy(mut self) -> Self {
+        self.output_option.stream_copy = true;
+        self
+    }
+
+    /// draw text on video, see `DrawText`
+    pub fn draw_text(mut self, text: DrawText) -> Self {
+        self.output_option.video_filters.push(text.to_filter());
+        self
     }
 
     pub fn video_filter(mut self, f: &str) -> Self {
@@ -156,58 +729,143 @@ impl FFmpegOutput {
         self
     }
 
+    /// change audio volume by `db` decibels
+    pub fn volume_db(self, db: f64) -> Self {
+        self.audio_filter(&format!("volume={db}dB"))
+    }
+
+    /// fade audio in from silence during the first `duration` seconds
+    pub fn fade_in(self, duration: f64) -> Self {
+        self.audio_filter(&format!("afade=t=in:st=0:d={duration}"))
+    }
+
+    /// fade audio out to silence from `start` seconds, lasting `duration` seconds
+    pub fn fade_out(self, start: f64, duration: f64) -> Self {
+        self.audio_filter(&format!("afade=t=out:st={start}:d={duration}"))
+    }
+
     pub fn audio_filters(mut self, filters: &[&str]) -> Self {
         let mut filters: Vec<String> = filters.iter().map(|item| (*item).to_owned()).collect();
         self.output_option.audio_filters.append(&mut filters);
         self
     }
 
+    /// add a chain to the complex filtergraph, which can reference streams
+    /// of every input, e.g. `[0:v][1:v]hstack`. it can not be combined with
+    /// `resize`, `crop`, `video_filter` or `audio_filter`, add those to the
+    /// graph instead
+    pub fn filter_complex(mut self, graph: &str) -> Self {
+        self.output_option.filter_complex.push(graph.to_owned());
+        self
+    }
+
     pub fn format(mut self, format: &str) -> Self {
         self.output_option.format = Some(format.to_owned());
         self
     }
 
+    /// container used for output written to a pipe, also applied to saved
+    /// files when set. without it, piped output falls back to the container
+    /// matching `format`, or to fragmented mp4 when no format is set
+    pub fn streaming_container(mut self, container: StreamingContainer) -> Self {
+        self.output_option.streaming_container = Some(container);
+        self
+    }
+
+    /// `Content-Type` of piped output, e.g. for http responses of `stream`
+    pub fn content_type(&self) -> Option<&'static str> {
+        self.pipe_container()
+            .map(|container| container.content_type())
+    }
+
+    fn pipe_container(&self) -> Option<StreamingContainer> {
+        if let Some(ref container) = self.output_option.streaming_container {
+            return Some(container.clone());
+        }
+        match self.output_option.format.as_deref() {
+            None | Some("mp4") => Some(StreamingContainer::fragmented_mp4()),
+            Some("mpegts") => Some(StreamingContainer::MpegTs),
+            Some("matroska") => Some(StreamingContainer::Matroska),
+            Some("webm") => Some(StreamingContainer::WebM),
+            Some("adts") => Some(StreamingContainer::Adts),
+            Some("ogg") => Some(StreamingContainer::Ogg),
+            Some(_) => None,
+        }
+    }
+
+    /// default is `OverwritePolicy::Overwrite`
+    pub fn overwrite(mut self, policy: OverwritePolicy) -> Self {
+        self.output_option.overwrite = Some(policy);
+        self
+    }
+
+    /// let `save` write straight to destination instead of a temp file, a
+    /// failed run then leaves a partial file behind
+    pub fn in_place(mut self) -> Self {
+        self.output_option.in_place = true;
+        self
+    }
+
+    /// keep ffmpeg's own log output and print the executed command to stderr
     pub fn verbose(mut self) -> Self {
         self.output_option.verbose = true;
         self
     }
 
     pub fn build_args(&mut self, output_file: Option<String>) -> Result<Vec<String>, Error> {
+        let overwrite = self.output_option.overwrite;
         let mut verbose_args = if !self.output_option.verbose {
-            owned!["-y", "-hide_banner", "-loglevel", "error"]
+            owned![
+                overwrite.unwrap_or_default().flag(),
+                "-hide_banner",
+                "-loglevel",
+                "error"
+            ]
         } else {
-            owned![]
+            overwrite.map_or(owned![], |policy| owned![policy.flag()])
         };
+        if let Some(threads) = self.output_option.filter_threads {
+            verbose_args.append(&mut owned!["-filter_threads", &threads.to_string()]);
+        }
 
         let inputs = &self.inputs.inputs;
         let merge_strategy = &self.inputs.merge_strategy;
 
         let mut input_args: Vec<String> = owned![];
         let mut output_args: Vec<String> = owned![];
+        let has_subtitles = !self.output_option.subtitles.is_empty();
+        let has_cover_art = self.output_option.cover_art.is_some();
+        // explicit maps disable automatic stream selection of ffmpeg, so other
+        // inputs have to be mapped explicitly as well
+        let mut implicit_maps = vec![];
+        if has_subtitles || has_cover_art || inputs.iter().any(|input| input.has_explicit_maps()) {
+            if !has_cover_art {
+                implicit_maps.push("v?");
+            }
+            implicit_maps.push("a?");
+            // output taking soft subtitles can carry those of inputs as well
+            if has_subtitles {
+                implicit_maps.push("s?");
+                let format = self.output_option.format.as_deref();
+                if output_file
+                    .as_deref()
+                    .is_some_and(|file| container::is_matroska(file, format))
+                {
+                    implicit_maps.push("t?");
+                }
+            }
+        }
 
         if let MergeStrategy::Merge = merge_strategy {
             for (idx, input) in inputs.iter().enumerate() {
-                let mut args = input.build_args().unwrap();
-                let stream_index = if let Some(s_idx) = input.stream_index {
-                    format!(":{s_idx}")
-                } else {
-                    "".to_owned()
-                };
-                match input.stream_type {
-                    StreamType::Audio => {
-                        output_args.append(&mut owned!["-map", &format!("{idx}:a{stream_index}")]);
-                    }
-                    StreamType::Video => {
-                        output_args.append(&mut owned!["-map", &format!("{idx}:v{stream_index}")]);
-                    }
-                    _ => (),
-                }
+                let mut args = input.build_args()?;
+                output_args.append(&mut input.build_maps(idx, &implicit_maps)?);
                 input_args.append(&mut args);
             }
         } else {
             input_args.append(&mut owned!["-f", "concat", "-safe", "0", "-i"]);
             let mut tempfile = tempfile::NamedTempFile::new()?;
-            for (_, input) in inputs.iter().enumerate() {
+            for input in inputs.iter() {
                 let file = input.get_input_file()?;
                 let file = PathBuf::from_str(&file)
                     .unwrap()
@@ -221,26 +879,148 @@ impl FFmpegOutput {
             tempfile.flush().unwrap();
             let tempfile_path = tempfile.path();
             let tempfile_path = tempfile_path.canonicalize().unwrap();
-            input_args.push(format!(
-                r#"{}"#,
-                tempfile_path.to_string_lossy().to_string()
-            ));
-            self.output_option.temp_input_filelist = Some(tempfile);
+            input_args.push(tempfile_path.to_string_lossy().to_string());
+            self.output_option.temp_input_filelist = Some(Arc::new(tempfile));
+            for stream in &implicit_maps {
+                output_args.append(&mut owned!["-map", &format!("0:{stream}")]);
+            }
         }
 
-        if let Some(size) = self.output_option.size {
+        let mut next_input = match merge_strategy {
+            MergeStrategy::Merge => inputs.len(),
+            MergeStrategy::Concat => 1,
+        };
+        for (n, track) in self.output_option.subtitles.iter().enumerate() {
+            let idx = next_input;
+            next_input += 1;
+            input_args.append(&mut owned!["-i", &track.file]);
+            output_args.append(&mut owned!["-map", &format!("{idx}:s:0")]);
+            if let Some(ref language) = track.language {
+                output_args.append(&mut owned![
+                    &format!("-metadata:s:s:{n}"),
+                    &format!("language={language}")
+                ]);
+            }
+            if let Some(ref title) = track.title {
+                output_args.append(&mut owned![
+                    &format!("-metadata:s:s:{n}"),
+                    &format!("title={title}")
+                ]);
+            }
+            output_args.append(&mut owned![
+                &format!("-disposition:s:{n}"),
+                &track.disposition()
+            ]);
+        }
+        let subtitle_codec = self.output_option.subtitle_codec.as_deref().or_else(|| {
+            let output_file = output_file.as_deref()?;
+            has_subtitles.then(|| subtitle::codec_for_output(output_file))?
+        });
+        if let Some(codec) = subtitle_codec {
+            output_args.append(&mut owned!["-c:s", codec]);
+        }
+
+        if !self.output_option.chapters.is_empty() {
+            let mut tempfile = tempfile::NamedTempFile::new()?;
+            let ffmetadata = metadata::to_ffmetadata(&self.output_option.chapters);
+            tempfile.write_all(ffmetadata.as_bytes())?;
+            tempfile.flush()?;
             input_args.append(&mut owned![
-                "-filter:v",
-                &format!("scale={}:{}", size.0, size.1)
+                "-f",
+                "ffmetadata",
+                "-i",
+                &tempfile.path().to_string_lossy()
+            ]);
+            output_args.append(&mut owned!["-map_chapters", &next_input.to_string()]);
+            next_input += 1;
+            self.output_option.temp_ffmetadata = Some(Arc::new(tempfile));
+        }
+
+        if let Some(ref cover_art) = self.output_option.cover_art {
+            input_args.append(&mut owned!["-i", cover_art]);
+            output_args.append(&mut owned![
+                "-map",
+                &format!("{next_input}:v:0"),
+                "-c:v",
+                "copy",
+                "-disposition:v:0",
+                "attached_pic"
             ]);
+            let is_mp3 = output_file
+                .as_deref()
+                .map(|file| file.to_lowercase().ends_with(".mp3"))
+                .unwrap_or(false);
+            if is_mp3 {
+                // id3v2.4 cover art is not recognised by many players
+                output_args.append(&mut owned!["-id3v2_version", "3"]);
+            }
+        }
+
+        match self.output_option.metadata_source {
+            Some(MetadataSource::Input(idx)) => {
+                output_args.append(&mut owned!["-map_metadata", &idx.to_string()]);
+            }
+            Some(MetadataSource::Strip) => {
+                output_args.append(&mut owned!["-map_metadata", "-1"]);
+            }
+            None => (),
+        }
+        for (key, value) in &self.output_option.metadata {
+            output_args.append(&mut owned!["-metadata", &format!("{key}={value}")]);
+        }
+        for (stream, key, value) in &self.output_option.stream_metadata {
+            output_args.append(&mut owned![
+                &format!("-metadata:s:{stream}"),
+                &format!("{key}={value}")
+            ]);
+        }
+        if self.output_option.stream_copy {
+            output_args.append(&mut owned!["-c", "copy"]);
+        }
+
+        // ffmpeg only honours the last -filter:v/-filter:a, so chain everything into one graph
+        let mut video_filters = vec![];
+        if let Some(crop) = self.output_option.crop {
+            video_filters.push(crop.to_filter());
+        }
+        if let Some(size) = self.output_option.size {
+            video_filters.push(format!("scale={}:{}", size.0, size.1));
+        }
+        video_filters.append(&mut self.output_option.video_filters.clone());
+        if !video_filters.is_empty() {
+            input_args.append(&mut owned!["-filter:v", &video_filters.join(",")]);
         }
 
-        for filter in &self.output_option.video_filters {
-            input_args.append(&mut owned!["-filter:v", filter]);
+        let mut audio_filters = self.output_option.audio_filters.clone();
+        if let Some(ref loudnorm) = self.output_option.loudnorm {
+            audio_filters.push(loudnorm.to_owned());
+        }
+        // ffmpeg refuses simple filters on streams fed by a complex graph
+        let has_simple_filters = !video_filters.is_empty() || !audio_filters.is_empty();
+        let has_filters = has_simple_filters || !self.output_option.filter_complex.is_empty();
+        if has_filters && self.output_option.stream_copy {
+            return Err(Error {
+                kind: ErrorKind::InvalidOption,
+                msg: "filters can not be used together with stream_copy".to_owned(),
+            });
+        }
+        if has_simple_filters && !self.output_option.filter_complex.is_empty() {
+            return Err(Error {
+                kind: ErrorKind::InvalidOption,
+                msg:
+                    "resize, crop, video and audio filters can not be combined with filter_complex"
+                        .to_owned(),
+            });
+        }
+        if !audio_filters.is_empty() {
+            input_args.append(&mut owned!["-filter:a", &audio_filters.join(",")]);
         }
 
-        for filter in &self.output_option.video_filters {
-            input_args.append(&mut owned!["-filter:a", filter]);
+        if !self.output_option.filter_complex.is_empty() {
+            input_args.append(&mut owned![
+                "-filter_complex",
+                &self.output_option.filter_complex.join(";")
+            ]);
         }
 
         if let Some(bitrate) = self.output_option.bitrate {
@@ -251,6 +1031,10 @@ impl FFmpegOutput {
             input_args.append(&mut owned!["-r", &framerate.to_string()]);
         }
 
+        if let Some(threads) = self.output_option.threads {
+            output_args.append(&mut owned!["-threads", &threads.to_string()]);
+        }
+
         let mut format_args = vec![];
         output_args.append(&mut format_args);
 
@@ -258,8 +1042,33 @@ impl FFmpegOutput {
             output_args.append(&mut owned!["-t", &timeout.to_string()]);
         }
 
-        if let Some(ref format) = self.output_option.format {
-            output_args.append(&mut owned!["-f", format]);
+        let custom_movflags = self
+            .output_option
+            .custom_args
+            .iter()
+            .any(|arg| arg == "-movflags");
+        match output_file {
+            _ if self.output_option.streaming_container.is_some() => {
+                output_args.append(&mut self.pipe_container().unwrap().muxer_args());
+            }
+            None => match self.pipe_container() {
+                Some(container) => output_args.append(&mut container.muxer_args()),
+                None => {
+                    let format = self.output_option.format.as_ref().unwrap();
+                    output_args.append(&mut owned!["-f", format]);
+                }
+            },
+            Some(ref file) => {
+                let format = self.output_option.format.as_deref();
+                if let Some(format) = format {
+                    output_args.append(&mut owned!["-f", format]);
+                }
+                // moov atom in front lets playback start before download ends
+                if !file.starts_with("pipe:") && !custom_movflags && container::is_mp4(file, format)
+                {
+                    output_args.append(&mut owned!["-movflags", "+faststart"]);
+                }
+            }
         }
 
         let mut ending_args = owned![];
@@ -277,7 +1086,6 @@ impl FFmpegOutput {
 
         input_args.append(&mut ending_args);
 
-        println!("{}", input_args.join(" "));
         Ok(input_args)
     }
     pub fn set_buffer_size(mut self, size: usize) -> Self {
@@ -296,152 +1104,446 @@ impl FFmpegOutput {
         self.output_option.size = Some((width, height));
         self
     }
-    #[cfg(feature = "async")]
+    /// crop video before it is resized, see `analysis::detect_crop`
+    pub fn crop(mut self, crop: Crop) -> Self {
+        self.output_option.crop = Some(crop);
+        self
+    }
+    /// run ffmpeg and read its output as a blocking `std::io::Read`, works
+    /// without any async runtime
+    ///
+    /// samples:
+    /// ```
+    /// # use ffmpeg_cli_utils::FFMpeg;
+    /// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+    /// let mut reader = FFMpeg::input("./sample.mp4")
+    ///     .output()
+    ///     .resize(-2, 320)
+    ///     .blocking_stream()
+    ///     .unwrap();
+    /// let mut file = std::fs::File::create("./output/blocking-stream.mp4").unwrap();
+    /// std::io::copy(&mut reader, &mut file).unwrap();
+    /// ```
+    pub fn blocking_stream(&mut self) -> Result<BlockingReader, Error> {
+        self.measure_loudness()?;
+        let ffmpeg_bin = FFMpeg::get_ffmpeg_bin();
+        let args = self.build_args(Option::<String>::None)?;
+        let mut child = self
+            .command(&ffmpeg_bin)?
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdout = child.stdout.take().unwrap();
+        let mut stderr = child.stderr.take().unwrap();
+        // stderr is drained aside so that ffmpeg never blocks on it
+        let stderr = thread::spawn(move || {
+            let mut err = String::new();
+            let _ = stderr.read_to_string(&mut err);
+            err
+        });
+        let limits = self.output_option.limits.clone();
+        let child = Arc::new(Mutex::new(child));
+        let watchdog = limits
+            .wall_timeout
+            .map(|timeout| Watchdog::start(child.clone(), timeout));
+        Ok(BlockingReader {
+            child,
+            stdout,
+            stderr: Some(stderr),
+            limits,
+            watchdog,
+            finished: None,
+        })
+    }
+
+    /// pump ffmpeg output straight into `sink`, e.g. a socket or an upload,
+    /// and return the number of bytes written along with exit status and stderr
+    /// of ffmpeg. container can not be inferred from `pipe:1`, so `format` is
+    /// required
+    ///
+    /// samples:
+    /// ```
+    /// # use ffmpeg_cli_utils::FFMpeg;
+    /// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+    /// let mut sink = std::fs::File::create("./output/write-to.mkv").unwrap();
+    /// let result = FFMpeg::input("./sample.mp4")
+    ///     .output()
+    ///     .format("matroska")
+    ///     .write_to(&mut sink)
+    ///     .unwrap();
+    /// println!("{} bytes written", result.bytes_written);
+    /// ```
+    pub fn write_to(&mut self, mut sink: impl Write) -> Result<WriteResult, Error> {
+        self.check_sink_format()?;
+        let mut reader = self.blocking_stream()?;
+        let mut buf = vec![0; self.output_option.stream_buffer_size];
+        let mut bytes_written = 0;
+        loop {
+            let size = reader.read(&mut buf).map_err(reader_error)?;
+            if size == 0 {
+                break;
+            }
+            sink.write_all(&buf[..size])?;
+            bytes_written += size as u64;
+        }
+        sink.flush()?;
+        let (status, stderr) = reader.finished.take().ok_or_else(ended_early)?;
+        Ok(WriteResult {
+            bytes_written,
+            status,
+            stderr,
+        })
+    }
+
+    /// async version of `write_to`, for any `futures::io::AsyncWrite`. tokio
+    /// writers can be adapted with `tokio_util::compat`
+    #[cfg(any(feature = "runtime-tokio", feature = "runtime-async-process"))]
+    pub async fn async_write_to<W>(&mut self, mut sink: W) -> Result<WriteResult, Error>
+    where
+        W: futures::io::AsyncWrite + Unpin,
+    {
+        self.check_sink_format()?;
+        let mut reader = self.stream()?;
+        let mut bytes_written = 0;
+        while let Some(chunk) = reader.next().await {
+            let chunk = chunk.map_err(reader_error)?;
+            // dropping reader on a failed write stops ffmpeg
+            sink.write_all(&chunk).await?;
+            bytes_written += chunk.len() as u64;
+        }
+        sink.flush().await?;
+        let (status, stderr) = reader.finished.take().ok_or_else(ended_early)?;
+        Ok(WriteResult {
+            bytes_written,
+            status,
+            stderr,
+        })
+    }
+
+    fn check_sink_format(&self) -> Result<(), Error> {
+        let format = match self.output_option.streaming_container {
+            Some(ref container) => Some(container.format()),
+            None => self.output_option.format.as_deref(),
+        };
+        let format = format.ok_or_else(|| Error {
+            kind: ErrorKind::InvalidOption,
+            msg: "output format is required when writing to a sink, it can not be inferred from pipe:1".to_owned(),
+        })?;
+        if !FFMpeg::muxers()?.iter().any(|muxer| muxer == format) {
+            return Err(Error {
+                kind: ErrorKind::InvalidOption,
+                msg: format!("unknown output format {format}"),
+            });
+        }
+        Ok(())
+    }
+
+    #[cfg(any(feature = "runtime-tokio", feature = "runtime-async-process"))]
     pub fn stream(&mut self) -> Result<Reader, Error> {
-        use tokio::sync::mpsc;
+        // a few chunks in flight at most, a slow consumer holds ffmpeg back
+        // instead of piling its output up in memory
+        let (w, r) = mpsc::channel::<ChannelData>(STREAM_CHANNEL_SIZE);
+        if self.loudness_measurement()?.is_some() {
+            // measurement pass would block the caller, so it runs in background
+            // on a copy of output that lives until the encode is done
+            let mut output = self.clone();
+            runtime::spawn_task(async move {
+                let mut err_w = w.clone();
+                let pump = match output.async_measure_loudness().await {
+                    Ok(()) => output.start_stream(w),
+                    Err(e) => Err(e),
+                };
+                match pump {
+                    Ok(pump) => pump.await,
+                    Err(e) => {
+                        let _ = err_w.send(ChannelData::Err(e)).await;
+                    }
+                }
+            });
+        } else {
+            runtime::spawn_task(self.start_stream(w)?);
+        }
+        Ok(Reader {
+            r,
+            cached: Bytes::new(),
+            done: false,
+            finished: None,
+        })
+    }
 
+    /// spawn ffmpeg writing to `pipe:1`, returned future pumps its output to `w`
+    #[cfg(any(feature = "runtime-tokio", feature = "runtime-async-process"))]
+    fn start_stream(
+        &mut self,
+        mut w: Sender<ChannelData>,
+    ) -> Result<impl std::future::Future<Output = ()> + Send + 'static, Error> {
         let buffer_max = self.output_option.stream_buffer_size;
-        let (w, r) = mpsc::channel::<ChannelData>(64);
         let ffmpeg_bin = FFMpeg::get_ffmpeg_bin();
-        let mut args = self.build_args(Option::<String>::None)?;
-        args.append(&mut owned!["-movflags", "frag_keyframe+empty_moov"]);
-        tokio::spawn(async move {
-            let mut child = process::Command::new(ffmpeg_bin)
-                .args(args)
-                .stdout(Stdio::piped())
-                .stderr(Stdio::piped())
-                .spawn()
-                .unwrap();
+        let args = self.build_args(Option::<String>::None)?;
+        let mut command = self.command(&ffmpeg_bin)?;
+        command.args(args);
+        let mut child = runtime::spawn(command)?;
+        let limits = self.output_option.limits.clone();
+        Ok(async move {
             let mut stdout = child.stdout.take().unwrap();
             let mut stderr = child.stderr.take().unwrap();
-            let mut buf = vec![0; buffer_max];
-            let mut err_str_buf = String::with_capacity(256);
-            let mut err_str = String::new();
 
-            loop {
-                let a = stdout.read(&mut buf);
-                let b = stderr.read_to_string(&mut err_str_buf);
-                let mut out_size = 0;
-                let mut err_size = 0;
-                tokio::select!(
-                    out = a => {
-                        match out {
-                            Ok(out_s) => {
-                                out_size = out_s;
-                            },
-                            Err(e) => {
-                                child.kill().await.unwrap();
-                                w.send(ChannelData::Err(e.to_string())).await.unwrap();
-                                break;
-                            }
-                        }
-                    },
-                    err = b => {
-                        match err {
-                            Ok(err_s) => {
-                                err_size = err_s;
-                            },
-                            Err(e) => {
-                                child.kill().await.unwrap();
-                                w.send(ChannelData::Err(e.to_string())).await.unwrap();
-                                break;
+            let mut err_str = String::new();
+            let pump_err = stderr.read_to_string(&mut err_str);
+            let mut out_w = w.clone();
+            let pump_out = async move {
+                let mut buf = BytesMut::new();
+                loop {
+                    // space of chunks already handed out is reclaimed once they are dropped
+                    buf.resize(buffer_max, 0);
+                    match stdout.read(&mut buf).await {
+                        Ok(0) => return Ok(()),
+                        Ok(size) => {
+                            let chunk = buf.split_to(size).freeze();
+                            if out_w.send(ChannelData::Data(chunk)).await.is_err() {
+                                return Err(Error {
+                                    kind: ErrorKind::Io,
+                                    msg: "reader dropped".to_owned(),
+                                });
                             }
                         }
+                        Err(e) => return Err(Error::from(e)),
                     }
-                );
-                // println!("pppp {out_size} {err_size}");
-                if out_size == 0 && err_size == 0 {
-                    if !err_str.is_empty() {
-                        w.send(ChannelData::Err(err_str)).await.unwrap();
-                    } else {
-                        w.send(ChannelData::End).await.unwrap();
-                    }
-                    break;
                 }
-                if out_size != 0 {
-                    let bytes = buf[0..out_size].to_vec();
-                    w.send(ChannelData::Data(bytes)).await.unwrap();
+            };
+            // stdout is dropped as soon as its pump ends, so ffmpeg stops on a
+            // broken pipe when the reader has gone away
+            let pumps = async { futures::join!(pump_out, pump_err).0 };
+            let (out_res, timed_out) = match runtime::timeout(limits.wall_timeout, pumps).await {
+                Some(out_res) => (out_res, false),
+                None => {
+                    let _ = child.kill().await;
+                    (Ok(()), true)
                 }
-                if err_size != 0 {
-                    err_str.push_str(&err_str_buf[0..err_size]);
+            };
+            let end = match out_res {
+                Err(e) => {
+                    let _ = child.kill().await;
+                    let _ = child.wait().await;
+                    ChannelData::Err(e)
                 }
+                Ok(()) => match child.wait().await {
+                    Ok(status) => match limits.exceeded(&status, &err_str, timed_out) {
+                        Some(e) => ChannelData::Err(e),
+                        None if status.success() && err_str.is_empty() => {
+                            ChannelData::End(status, err_str)
+                        }
+                        None if err_str.is_empty() => ChannelData::Err(Error {
+                            kind: ErrorKind::Ffmpeg,
+                            msg: format!("ffmpeg exited with {status}"),
+                        }),
+                        None => ChannelData::Err(Error {
+                            kind: ErrorKind::Ffmpeg,
+                            msg: err_str,
+                        }),
+                    },
+                    Err(e) => ChannelData::Err(e.into()),
+                },
+            };
+            let _ = w.send(end).await;
+        })
+    }
+}
+
+/// blocking reader of ffmpeg output, returned by `FFmpegOutput::blocking_stream`
+///
+/// reaching end of output waits for ffmpeg to exit, its failure is returned
+/// as the last read error. ffmpeg is killed when reader is dropped early
+pub struct BlockingReader {
+    /// shared with watchdog of `wall_timeout`
+    child: Arc<Mutex<Child>>,
+    stdout: ChildStdout,
+    stderr: Option<thread::JoinHandle<String>>,
+    limits: Limits,
+    watchdog: Option<Watchdog>,
+    /// status and stderr of ffmpeg, once it exited successfully
+    finished: Option<(ExitStatus, String)>,
+}
+
+impl BlockingReader {
+    /// wait for ffmpeg without holding the child, so that watchdog can still
+    /// kill it. returns whether watchdog killed it before it exited
+    fn wait(&mut self) -> std::io::Result<(std::process::ExitStatus, bool)> {
+        let status = loop {
+            if let Some(status) = self.child.lock().unwrap().try_wait()? {
+                break status;
             }
-        });
-        let r = Reader {
-            r: Box::pin(r),
-            cached: Box::pin(vec![]),
-            read: 0,
+            thread::sleep(time::Duration::from_millis(20));
         };
-        Ok(r)
+        let timed_out = self
+            .watchdog
+            .as_mut()
+            .is_some_and(|watchdog| watchdog.stop());
+        Ok((status, timed_out))
+    }
+}
+
+impl Read for BlockingReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let size = self.stdout.read(buf)?;
+        if size > 0 || buf.is_empty() {
+            return Ok(size);
+        }
+        let stderr = match self.stderr.take() {
+            Some(stderr) => stderr.join().unwrap_or_default(),
+            None => return Ok(0),
+        };
+        let (status, timed_out) = self.wait()?;
+        // `write_to` takes error back out of io error, keeping its kind
+        if let Some(e) = self.limits.exceeded(&status, &stderr, timed_out) {
+            return Err(std::io::Error::other(e));
+        }
+        if !status.success() || !stderr.is_empty() {
+            return Err(std::io::Error::other(Error {
+                kind: ErrorKind::Ffmpeg,
+                msg: stderr,
+            }));
+        }
+        self.finished = Some((status, stderr));
+        Ok(0)
     }
 }
 
+impl Drop for BlockingReader {
+    fn drop(&mut self) {
+        if self.stderr.is_some() {
+            let mut child = self.child.lock().unwrap();
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-process"))]
+const STREAM_CHANNEL_SIZE: usize = 4;
+
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-process"))]
 #[derive(Debug)]
 enum ChannelData {
-    Data(Vec<u8>),
-    Err(String),
-    End,
+    Data(Bytes),
+    Err(Error),
+    End(ExitStatus, String),
 }
 
+/// async reader of ffmpeg output, returned by `FFmpegOutput::stream`
+///
+/// chunks can be taken without copy as a `Stream` of `Bytes`, or read through
+/// `futures::io::AsyncRead` and, with `runtime-tokio`, `tokio::io::AsyncRead`.
+/// output ends with EOF when ffmpeg succeeds, otherwise its failure is the
+/// last item. ffmpeg is killed when reader is dropped early
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-process"))]
 pub struct Reader {
-    r: Pin<Box<Receiver<ChannelData>>>,
-    cached: Pin<Box<Vec<u8>>>,
-    read: usize,
+    r: Receiver<ChannelData>,
+    cached: Bytes,
+    done: bool,
+    /// status and stderr of ffmpeg, once it exited successfully
+    finished: Option<(ExitStatus, String)>,
+}
+
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-process"))]
+impl Reader {
+    fn poll_chunk(&mut self, cx: &mut Context<'_>) -> Poll<Option<std::io::Result<Bytes>>> {
+        if !self.cached.is_empty() {
+            return Poll::Ready(Some(Ok(std::mem::take(&mut self.cached))));
+        }
+        if self.done {
+            return Poll::Ready(None);
+        }
+        let item = match ready!(Pin::new(&mut self.r).poll_next(cx)) {
+            Some(ChannelData::Data(data)) => return Poll::Ready(Some(Ok(data))),
+            Some(ChannelData::Err(err)) => Some(Err(std::io::Error::other(err))),
+            Some(ChannelData::End(status, stderr)) => {
+                self.finished = Some((status, stderr));
+                None
+            }
+            None => Some(Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "ffmpeg output ended unexpectedly",
+            ))),
+        };
+        self.done = true;
+        Poll::Ready(item)
+    }
+
+    fn poll_fill(&mut self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<std::io::Result<usize>> {
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let mut chunk = match ready!(self.poll_chunk(cx)) {
+            Some(Ok(chunk)) => chunk,
+            Some(Err(e)) => return Poll::Ready(Err(e)),
+            None => return Poll::Ready(Ok(0)),
+        };
+        let size = cmp::min(chunk.len(), buf.len());
+        buf[..size].copy_from_slice(&chunk.split_to(size));
+        self.cached = chunk;
+        Poll::Ready(Ok(size))
+    }
+}
+
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-process"))]
+impl Stream for Reader {
+    type Item = std::io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.poll_chunk(cx)
+    }
 }
 
-impl AsyncRead for Reader {
+#[cfg(any(feature = "runtime-tokio", feature = "runtime-async-process"))]
+impl futures::io::AsyncRead for Reader {
     fn poll_read(
-        mut self: std::pin::Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        self.poll_fill(cx, buf)
+    }
+}
+
+#[cfg(feature = "runtime-tokio")]
+impl tokio::io::AsyncRead for Reader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
         buf: &mut tokio::io::ReadBuf<'_>,
-    ) -> std::task::Poll<std::io::Result<()>> {
-        let cached_len = self.cached.len();
-        if cached_len > 0 {
-            let remain = buf.remaining();
-            let min = cmp::min(cached_len, remain);
-            let to_fill: Vec<u8> = self.cached.drain(0..min).collect();
-            self.read += to_fill.len();
-            buf.put_slice(&to_fill);
-            return Poll::Ready(Ok(()));
-        }
-        let r = self.r.poll_recv(cx);
-        let cached = &mut self.cached;
-        match r {
-            Poll::Ready(val) => match val {
-                Some(val) => match val {
-                    ChannelData::Data(mut data) => {
-                        cached.append(&mut data);
-                        let remain = buf.remaining();
-                        let len = cached.len();
-                        if len == 0 {
-                            return Poll::Ready(Ok(()));
-                        }
-                        let min = cmp::min(len, remain);
-                        let to_fill: Vec<u8> = cached.drain(0..min).collect();
-                        self.read += to_fill.len();
-                        buf.put_slice(&to_fill);
-                        Poll::Ready(Ok(()))
-                    }
-                    ChannelData::Err(err) => Poll::Ready(Err(std::io::Error::new(
-                        std::io::ErrorKind::Interrupted,
-                        err,
-                    ))),
-                    ChannelData::End => {
-                        let remain = buf.remaining();
-                        let len = cached.len();
-                        if len == 0 {
-                            return Poll::Ready(Ok(()));
-                        }
-                        let min = cmp::min(len, remain);
-                        let to_fill: Vec<u8> = cached.drain(0..min).collect();
-                        self.read += to_fill.len();
-                        buf.put_slice(&to_fill);
-                        Poll::Ready(Ok(()))
-                    }
-                },
-                None => Poll::Pending,
-            },
-            Poll::Pending => Poll::Pending,
+    ) -> Poll<std::io::Result<()>> {
+        let filled = self.poll_fill(cx, buf.initialize_unfilled());
+        if let Poll::Ready(Ok(size)) = filled {
+            buf.advance(size);
         }
+        filled.map_ok(|_| ())
+    }
+}
+
+/// error of ffmpeg carried by an io error of a reader, with its original kind
+fn reader_error(e: std::io::Error) -> Error {
+    match e.get_ref().and_then(|inner| inner.downcast_ref::<Error>()) {
+        Some(inner) => inner.clone(),
+        None => Error {
+            kind: ErrorKind::Ffmpeg,
+            msg: e.to_string(),
+        },
+    }
+}
+
+/// output of a sink ended without ffmpeg exiting
+fn ended_early() -> Error {
+    Error {
+        kind: ErrorKind::UnexpectedOutput,
+        msg: "ffmpeg output ended before ffmpeg exited".to_owned(),
+    }
+}
+
+fn already_exists(file: &str) -> Error {
+    Error {
+        kind: ErrorKind::Io,
+        msg: format!("{file} already exists"),
     }
 }
@@ -0,0 +1,523 @@
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    io::{BufRead, BufReader},
+    sync::{
+        atomic::{self, AtomicBool},
+        mpsc, Arc, Condvar, Mutex,
+    },
+    thread, time,
+};
+
+use futures::channel::oneshot;
+
+use crate::{
+    error::{Error, ErrorKind},
+    output::FFmpegOutput,
+    owned,
+};
+
+pub type JobId = u64;
+
+const POLL_INTERVAL: time::Duration = time::Duration::from_millis(50);
+
+/// messages of ffmpeg failures that may pass when tried again, mostly network
+/// errors of remote inputs and outputs
+const TRANSIENT_ERRORS: &[&str] = &[
+    "Connection reset",
+    "Connection refused",
+    "Connection timed out",
+    "timed out",
+    "Resource temporarily unavailable",
+    "Temporary failure in name resolution",
+    "Server returned 5",
+    "Broken pipe",
+];
+
+/// transcode to run on a `JobQueue`, `output` is saved to `file`
+pub struct Job {
+    output: FFmpegOutput,
+    file: String,
+    priority: i32,
+}
+
+impl Job {
+    pub fn new(output: FFmpegOutput, file: &str) -> Self {
+        Self {
+            output,
+            file: file.to_owned(),
+            priority: 0,
+        }
+    }
+
+    /// jobs of higher priority start first, jobs of same priority start in
+    /// order they are pushed. default is 0
+    pub fn priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+}
+
+/// lifecycle of jobs, received from `JobQueue::subscribe`
+#[derive(Clone, Debug)]
+pub enum JobEvent {
+    Queued {
+        id: JobId,
+    },
+    /// ffmpeg is started, `attempt` counts from 1
+    Started {
+        id: JobId,
+        attempt: u32,
+    },
+    /// position in output reached by ffmpeg
+    Progress {
+        id: JobId,
+        time: time::Duration,
+    },
+    /// attempt failed and job is started again after `delay`
+    Retrying {
+        id: JobId,
+        attempt: u32,
+        delay: time::Duration,
+        error: Error,
+    },
+    Finished {
+        id: JobId,
+    },
+    Failed {
+        id: JobId,
+        error: Error,
+    },
+    Cancelled {
+        id: JobId,
+    },
+}
+
+/// how failed jobs are retried, delay between attempts doubles from `backoff`
+/// up to `max_backoff`
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::queue::{JobQueue, RetryPolicy};
+/// use std::time::Duration;
+/// let queue = JobQueue::new(4).retry(
+///     RetryPolicy::new(3, Duration::from_secs(1))
+///         .retry_if(|error| error.msg.contains("Server returned 503")),
+/// );
+/// ```
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_retries: u32,
+    backoff: time::Duration,
+    max_backoff: time::Duration,
+    retry_if: Arc<dyn Fn(&Error) -> bool + Send + Sync>,
+}
+
+impl RetryPolicy {
+    /// retry up to `max_retries` times, only failures passing `is_transient`
+    /// are retried unless `retry_if` is given
+    pub fn new(max_retries: u32, backoff: time::Duration) -> Self {
+        Self {
+            max_retries,
+            backoff,
+            max_backoff: time::Duration::from_secs(60),
+            retry_if: Arc::new(is_transient),
+        }
+    }
+
+    pub fn max_backoff(mut self, max_backoff: time::Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    pub fn retry_if(mut self, f: impl Fn(&Error) -> bool + Send + Sync + 'static) -> Self {
+        self.retry_if = Arc::new(f);
+        self
+    }
+
+    fn delay(&self, attempt: u32) -> time::Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |delay| delay.min(self.max_backoff))
+    }
+}
+
+impl Default for RetryPolicy {
+    /// no retry
+    fn default() -> Self {
+        Self::new(0, time::Duration::from_secs(1))
+    }
+}
+
+/// whether `error` looks like a temporary failure, e.g. a dropped connection
+/// to a remote input
+pub fn is_transient(error: &Error) -> bool {
+    matches!(error.kind, ErrorKind::Io | ErrorKind::Ffmpeg)
+        && TRANSIENT_ERRORS
+            .iter()
+            .any(|pattern| error.msg.contains(pattern))
+}
+
+/// runs `Job`s in background threads with at most `max_concurrent` ffmpeg
+/// processes at once. queued jobs are cancelled when queue is dropped, running
+/// ones are left to finish
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::{queue::{Job, JobEvent, JobQueue}, FFMpeg};
+/// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+/// let queue = JobQueue::new(2).total_threads(8);
+/// let events = queue.subscribe();
+/// let thumb = queue.push(Job::new(
+///     FFMpeg::input("./sample.mp4").output().resize(-2, 320),
+///     "./output/queue-320p.mp4",
+/// ).priority(10));
+/// let full = queue.push(Job::new(FFMpeg::input("./sample.mp4").output(), "./output/queue.mkv"));
+/// thumb.wait().unwrap();
+/// full.cancel();
+/// for event in events.try_iter() {
+///     if let JobEvent::Progress { id, time } = event {
+///         println!("job {id} at {time:?}");
+///     }
+/// }
+/// ```
+pub struct JobQueue {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+    available: Condvar,
+    subscribers: Mutex<Vec<mpsc::Sender<JobEvent>>>,
+}
+
+struct State {
+    queue: BinaryHeap<QueuedJob>,
+    next_id: JobId,
+    max_concurrent: usize,
+    total_threads: Option<usize>,
+    retry: RetryPolicy,
+    shutdown: bool,
+}
+
+struct QueuedJob {
+    id: JobId,
+    job: Job,
+    cancel: Arc<AtomicBool>,
+    done: oneshot::Sender<Result<(), Error>>,
+}
+
+impl PartialEq for QueuedJob {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueuedJob {}
+
+impl PartialOrd for QueuedJob {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueuedJob {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.job
+            .priority
+            .cmp(&other.job.priority)
+            .then_with(|| other.id.cmp(&self.id))
+    }
+}
+
+impl JobQueue {
+    /// run at most `max_concurrent` jobs at a time, at least one
+    pub fn new(max_concurrent: usize) -> Self {
+        let max_concurrent = max_concurrent.max(1);
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                queue: BinaryHeap::new(),
+                next_id: 0,
+                max_concurrent,
+                total_threads: None,
+                retry: RetryPolicy::default(),
+                shutdown: false,
+            }),
+            available: Condvar::new(),
+            subscribers: Mutex::new(vec![]),
+        });
+        for _ in 0..max_concurrent {
+            let shared = shared.clone();
+            thread::spawn(move || work(shared));
+        }
+        JobQueue { shared }
+    }
+
+    /// share `threads` between running jobs, each job gets `-threads` of
+    /// `threads / max_concurrent`
+    pub fn total_threads(self, threads: usize) -> Self {
+        self.shared.state.lock().unwrap().total_threads = Some(threads);
+        self
+    }
+
+    pub fn retry(self, retry: RetryPolicy) -> Self {
+        self.shared.state.lock().unwrap().retry = retry;
+        self
+    }
+
+    /// receive events of all jobs pushed from now on
+    pub fn subscribe(&self) -> mpsc::Receiver<JobEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.shared.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    pub fn push(&self, mut job: Job) -> JobHandle {
+        let (done, finished) = oneshot::channel();
+        let cancel = Arc::new(AtomicBool::new(false));
+        let mut state = self.shared.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        if let Some(total) = state.total_threads {
+            let threads = (total / state.max_concurrent).max(1);
+            if !job.output.has_threads() {
+                job.output = job.output.threads(threads);
+            }
+        }
+        state.queue.push(QueuedJob {
+            id,
+            job,
+            cancel: cancel.clone(),
+            done,
+        });
+        self.shared.emit(JobEvent::Queued { id });
+        drop(state);
+        self.shared.available.notify_one();
+        JobHandle {
+            id,
+            cancel,
+            shared: self.shared.clone(),
+            finished,
+        }
+    }
+
+    /// number of jobs waiting for a free slot
+    pub fn queued(&self) -> usize {
+        self.shared.state.lock().unwrap().queue.len()
+    }
+}
+
+impl Drop for JobQueue {
+    fn drop(&mut self) {
+        let mut state = self.shared.state.lock().unwrap();
+        state.shutdown = true;
+        for queued in state.queue.drain() {
+            self.shared.cancelled(queued);
+        }
+        drop(state);
+        self.shared.available.notify_all();
+    }
+}
+
+/// handle of a pushed job
+pub struct JobHandle {
+    id: JobId,
+    cancel: Arc<AtomicBool>,
+    shared: Arc<Shared>,
+    finished: oneshot::Receiver<Result<(), Error>>,
+}
+
+impl JobHandle {
+    pub fn id(&self) -> JobId {
+        self.id
+    }
+
+    /// drop job if it is still queued, or kill its ffmpeg if it is running
+    pub fn cancel(&self) {
+        self.cancel.store(true, atomic::Ordering::SeqCst);
+        let mut state = self.shared.state.lock().unwrap();
+        let mut queue = std::mem::take(&mut state.queue).into_vec();
+        if let Some(pos) = queue.iter().position(|queued| queued.id == self.id) {
+            self.shared.cancelled(queue.swap_remove(pos));
+        }
+        state.queue = queue.into();
+    }
+
+    /// block until job is done
+    pub fn wait(self) -> Result<(), Error> {
+        futures::executor::block_on(self.finished())
+    }
+
+    /// wait until job is done, works on any executor
+    pub async fn finished(self) -> Result<(), Error> {
+        self.finished.await.unwrap_or_else(|_| Err(cancelled()))
+    }
+}
+
+impl Shared {
+    fn emit(&self, event: JobEvent) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+
+    fn cancelled(&self, queued: QueuedJob) {
+        self.emit(JobEvent::Cancelled { id: queued.id });
+        let _ = queued.done.send(Err(cancelled()));
+    }
+}
+
+fn cancelled() -> Error {
+    Error {
+        kind: ErrorKind::Cancelled,
+        msg: "job cancelled".to_owned(),
+    }
+}
+
+fn work(shared: Arc<Shared>) {
+    loop {
+        let (mut queued, retry) = {
+            let mut state = shared.state.lock().unwrap();
+            loop {
+                if let Some(queued) = state.queue.pop() {
+                    break (queued, state.retry.clone());
+                }
+                if state.shutdown {
+                    return;
+                }
+                state = shared.available.wait(state).unwrap();
+            }
+        };
+        let result = run_with_retry(&shared, &mut queued, &retry);
+        match result {
+            Ok(()) => shared.emit(JobEvent::Finished { id: queued.id }),
+            Err(ref e) if e.kind == ErrorKind::Cancelled => {
+                shared.emit(JobEvent::Cancelled { id: queued.id })
+            }
+            Err(ref e) => shared.emit(JobEvent::Failed {
+                id: queued.id,
+                error: e.clone(),
+            }),
+        }
+        let _ = queued.done.send(result);
+    }
+}
+
+fn run_with_retry(
+    shared: &Shared,
+    queued: &mut QueuedJob,
+    retry: &RetryPolicy,
+) -> Result<(), Error> {
+    let mut attempt = 1;
+    loop {
+        if queued.cancel.load(atomic::Ordering::SeqCst) {
+            return Err(cancelled());
+        }
+        shared.emit(JobEvent::Started {
+            id: queued.id,
+            attempt,
+        });
+        let error = match run_once(shared, queued) {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+        if error.kind == ErrorKind::Cancelled
+            || attempt > retry.max_retries
+            || !(retry.retry_if)(&error)
+        {
+            return Err(error);
+        }
+        let delay = retry.delay(attempt);
+        shared.emit(JobEvent::Retrying {
+            id: queued.id,
+            attempt,
+            delay,
+            error,
+        });
+        let wake = time::Instant::now() + delay;
+        while time::Instant::now() < wake && !queued.cancel.load(atomic::Ordering::SeqCst) {
+            thread::sleep(POLL_INTERVAL.min(wake - time::Instant::now()));
+        }
+        attempt += 1;
+    }
+}
+
+fn run_once(shared: &Shared, queued: &mut QueuedJob) -> Result<(), Error> {
+    let id = queued.id;
+    let global_args = owned!["-progress", "pipe:2", "-nostats"];
+    // temp file is removed when dropped on failure or cancel
+    let (mut child, temp) = queued.job.output.spawn(&queued.job.file, global_args)?;
+    let wall_timeout = queued.job.output.limits().wall_timeout;
+    let stderr = child.stderr.take().unwrap();
+    // progress reports and errors share stderr, reports are `key=value` lines
+    let errors = thread::scope(|scope| {
+        let reader = scope.spawn(|| {
+            let mut errors = String::new();
+            for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+                match line.split_once('=') {
+                    Some(("out_time_us", value)) => {
+                        if let Ok(us) = value.trim().parse::<u64>() {
+                            shared.emit(JobEvent::Progress {
+                                id,
+                                time: time::Duration::from_micros(us),
+                            });
+                        }
+                    }
+                    Some((key, _))
+                        if key
+                            .chars()
+                            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') => {}
+                    _ => {
+                        errors.push_str(&line);
+                        errors.push('\n');
+                    }
+                }
+            }
+            errors
+        });
+        let deadline = wall_timeout.map(|timeout| time::Instant::now() + timeout);
+        let mut timed_out = false;
+        let status = loop {
+            if queued.cancel.load(atomic::Ordering::SeqCst) {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(cancelled());
+            }
+            if deadline.is_some_and(|deadline| time::Instant::now() >= deadline) {
+                let _ = child.kill();
+                timed_out = true;
+                break child.wait();
+            }
+            match child.try_wait() {
+                Ok(Some(status)) => break Ok(status),
+                Ok(None) => thread::sleep(POLL_INTERVAL),
+                Err(e) => break Err(e),
+            }
+        };
+        let errors = reader.join().unwrap_or_default();
+        Ok((status, errors, timed_out))
+    });
+    let (status, errors, timed_out) = errors?;
+    let status = status?;
+    if let Some(e) = queued
+        .job
+        .output
+        .limits()
+        .exceeded(&status, &errors, timed_out)
+    {
+        return Err(e);
+    }
+    if !status.success() {
+        return Err(Error {
+            kind: ErrorKind::Ffmpeg,
+            msg: if errors.is_empty() {
+                format!("ffmpeg exited with {status}")
+            } else {
+                errors
+            },
+        });
+    }
+    queued.job.output.persist_output(temp, &queued.job.file)
+}
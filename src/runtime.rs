@@ -0,0 +1,163 @@
+//! process spawning and io used by the async api, backed by tokio
+//! (`runtime-tokio`) or by async-process on any executor
+//! (`runtime-async-process`). tokio wins when both features are enabled
+
+use std::{
+    future::Future,
+    io,
+    pin::Pin,
+    process::{ExitStatus, Stdio},
+    time,
+};
+
+use futures::{future, io::AsyncRead};
+
+pub(crate) type BoxedRead = Pin<Box<dyn AsyncRead + Send>>;
+
+/// spawned ffmpeg process, stdout and stderr are taken out as
+/// `futures::io::AsyncRead` whatever the backend is
+pub(crate) struct Child {
+    inner: imp::Child,
+    pub stdout: Option<BoxedRead>,
+    pub stderr: Option<BoxedRead>,
+}
+
+/// spawn `command` with stdout and stderr piped, its args and settings such
+/// as `pre_exec` are kept
+pub(crate) fn spawn(command: std::process::Command) -> io::Result<Child> {
+    let mut inner = imp::Command::from(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let stdout = inner.stdout.take().map(imp::boxed);
+    let stderr = inner.stderr.take().map(imp::boxed);
+    Ok(Child {
+        inner,
+        stdout,
+        stderr,
+    })
+}
+
+impl Child {
+    pub async fn kill(&mut self) -> io::Result<()> {
+        imp::kill(&mut self.inner).await
+    }
+
+    pub async fn wait(&mut self) -> io::Result<ExitStatus> {
+        imp::wait(&mut self.inner).await
+    }
+}
+
+/// run `task` in background, on tokio it is a tokio task, otherwise it is
+/// driven by its own thread since there is no executor to hand it to
+pub(crate) fn spawn_task<F>(task: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    imp::spawn_task(task)
+}
+
+/// run `task` until done or until `timeout` has passed, `None` on timeout
+pub(crate) async fn timeout<F: Future>(
+    timeout: Option<time::Duration>,
+    task: F,
+) -> Option<F::Output> {
+    let Some(timeout) = timeout else {
+        return Some(task.await);
+    };
+    let task = std::pin::pin!(task);
+    let sleep = std::pin::pin!(imp::sleep(timeout));
+    match future::select(task, sleep).await {
+        future::Either::Left((output, _)) => Some(output),
+        future::Either::Right(_) => None,
+    }
+}
+
+#[cfg(feature = "runtime-tokio")]
+mod imp {
+    use std::{
+        future::Future,
+        io,
+        pin::Pin,
+        process::ExitStatus,
+        task::{Context, Poll},
+    };
+
+    use super::BoxedRead;
+    use tokio::io::ReadBuf;
+    pub(super) use tokio::process::{Child, Command};
+
+    /// adapts tokio's `AsyncRead` to the futures one
+    struct Compat<T>(T);
+
+    impl<T: tokio::io::AsyncRead + Unpin> futures::io::AsyncRead for Compat<T> {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut [u8],
+        ) -> Poll<io::Result<usize>> {
+            let mut buf = ReadBuf::new(buf);
+            match Pin::new(&mut self.0).poll_read(cx, &mut buf) {
+                Poll::Ready(Ok(())) => Poll::Ready(Ok(buf.filled().len())),
+                Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+                Poll::Pending => Poll::Pending,
+            }
+        }
+    }
+
+    pub(super) fn boxed<T: tokio::io::AsyncRead + Unpin + Send + 'static>(r: T) -> BoxedRead {
+        Box::pin(Compat(r))
+    }
+
+    pub(super) async fn kill(child: &mut Child) -> io::Result<()> {
+        child.kill().await
+    }
+
+    pub(super) async fn wait(child: &mut Child) -> io::Result<ExitStatus> {
+        child.wait().await
+    }
+
+    pub(super) fn spawn_task<F>(task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        tokio::spawn(task);
+    }
+
+    pub(super) async fn sleep(duration: std::time::Duration) {
+        tokio::time::sleep(duration).await
+    }
+}
+
+#[cfg(all(feature = "runtime-async-process", not(feature = "runtime-tokio")))]
+mod imp {
+    use std::{future::Future, io, process::ExitStatus, thread, time};
+
+    use super::BoxedRead;
+    pub(super) use async_process::{Child, Command};
+
+    pub(super) fn boxed<T: futures::io::AsyncRead + Send + 'static>(r: T) -> BoxedRead {
+        Box::pin(r)
+    }
+
+    pub(super) async fn kill(child: &mut Child) -> io::Result<()> {
+        child.kill()
+    }
+
+    pub(super) async fn wait(child: &mut Child) -> io::Result<ExitStatus> {
+        child.status().await
+    }
+
+    pub(super) fn spawn_task<F>(task: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        thread::spawn(move || futures::executor::block_on(task));
+    }
+
+    /// timer of async-io, driven by its reactor on any executor
+    pub(super) async fn sleep(duration: time::Duration) {
+        async_io::Timer::after(duration).await;
+    }
+}
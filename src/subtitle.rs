@@ -0,0 +1,118 @@
+use std::path::Path;
+
+use crate::{input::StreamType, output::FFmpegOutput, FFMpeg};
+
+/// external subtitle file added to output as a soft subtitle track
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::{subtitle::SubtitleTrack, FFMpeg};
+/// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+/// FFMpeg::input("./sample.mp4")
+///     .output()
+///     .add_subtitle(SubtitleTrack::new("./sample.en.srt").language("eng").default())
+///     .add_subtitle(SubtitleTrack::new("./sample.zh.ass").language("chi").title("中文"))
+///     .save("./output/with_subtitles.mkv")
+///     .unwrap();
+/// ```
+#[derive(Clone, Debug)]
+pub struct SubtitleTrack {
+    pub(crate) file: String,
+    pub(crate) language: Option<String>,
+    pub(crate) title: Option<String>,
+    pub(crate) default: bool,
+    pub(crate) forced: bool,
+}
+
+impl SubtitleTrack {
+    pub fn new(file: &str) -> Self {
+        Self {
+            file: file.to_owned(),
+            language: None,
+            title: None,
+            default: false,
+            forced: false,
+        }
+    }
+
+    /// ISO 639-2 language code, e.g. `eng`
+    pub fn language(mut self, language: &str) -> Self {
+        self.language = Some(language.to_owned());
+        self
+    }
+
+    pub fn title(mut self, title: &str) -> Self {
+        self.title = Some(title.to_owned());
+        self
+    }
+
+    /// mark track as default subtitle
+    pub fn default(mut self) -> Self {
+        self.default = true;
+        self
+    }
+
+    /// mark track as forced subtitle
+    pub fn forced(mut self) -> Self {
+        self.forced = true;
+        self
+    }
+
+    pub(crate) fn disposition(&self) -> String {
+        match (self.default, self.forced) {
+            (true, true) => "default+forced",
+            (true, false) => "default",
+            (false, true) => "forced",
+            (false, false) => "0",
+        }
+        .to_owned()
+    }
+}
+
+/// subtitle codec a container can hold, picked by extension of output file
+pub(crate) fn codec_for_output(file: &str) -> Option<&'static str> {
+    let ext = Path::new(file)
+        .extension()?
+        .to_string_lossy()
+        .to_lowercase();
+    match ext.as_str() {
+        "mp4" | "m4v" | "mov" => Some("mov_text"),
+        "webm" => Some("webvtt"),
+        _ => None,
+    }
+}
+
+/// extract embedded subtitle stream of input file, format of output is
+/// decided by extension of the file it is saved to
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::subtitle;
+/// # use ffmpeg_cli_utils::FFMpeg;
+/// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+/// subtitle::extract("./output/with_subtitles.mkv", 0)
+///     .save("./output/extracted.srt")
+///     .unwrap();
+/// ```
+pub fn extract(file: &str, stream_index: u64) -> FFmpegOutput {
+    FFMpeg::input(file)
+        .stream_type(StreamType::Subtitle)
+        .take_stream(stream_index)
+        .output()
+}
+
+/// convert subtitle file between srt, ass and vtt by extension of the file it
+/// is saved to
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::subtitle;
+/// # use ffmpeg_cli_utils::FFMpeg;
+/// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+/// subtitle::convert("./output/extracted.srt")
+///     .save("./output/extracted.vtt")
+///     .unwrap();
+/// ```
+pub fn convert(file: &str) -> FFmpegOutput {
+    extract(file, 0)
+}
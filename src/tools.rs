@@ -1,5 +1,14 @@
-use crate::FFMpeg;
-use std::time;
+use crate::{
+    analysis,
+    error::{Error, ErrorKind},
+    input::{FFMpegInput, FFMpegMultipleInput},
+    utils::{
+        escape_filter_value, expand_sequence_pattern, format_vtt_time, parse_duration,
+        parse_output_video_size, parse_progress_frames, split_jpeg_images,
+    },
+    FFMpeg,
+};
+use std::{path::Path, time};
 
 /// screenshot of input video
 ///
@@ -58,3 +67,969 @@ pub fn capture_screen() -> crate::output::FFmpegOutput {
         .output()
 }
 
+/// thumbnail sprite sheets with a WebVTT track for player hover previews
+pub struct SpriteSheet {
+    file: String,
+    interval: time::Duration,
+    tile_cols: u32,
+    tile_rows: u32,
+    thumb_width: u32,
+    url_prefix: String,
+}
+
+pub struct SpriteSheetOutput {
+    /// paths of generated sprite images
+    pub sprites: Vec<String>,
+    /// path of generated WebVTT thumbnails track
+    pub vtt: String,
+}
+
+/// generate sprite sheets of thumbnails taken every `interval`, each sprite
+/// contains `tile_cols` x `tile_rows` thumbnails of `thumb_width` pixels wide
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::tools;
+/// use std::time;
+/// # use ffmpeg_cli_utils::FFMpeg;
+/// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+/// tools::sprite_sheet("./sample.mp4", &time::Duration::from_secs(5), 5, 5, 160)
+///     .save("./output/sprites")
+///     .unwrap();
+/// ```
+pub fn sprite_sheet(
+    file: &str,
+    interval: &time::Duration,
+    tile_cols: u32,
+    tile_rows: u32,
+    thumb_width: u32,
+) -> SpriteSheet {
+    SpriteSheet {
+        file: file.to_owned(),
+        interval: *interval,
+        tile_cols,
+        tile_rows,
+        thumb_width,
+        url_prefix: String::new(),
+    }
+}
+
+impl SpriteSheet {
+    /// prefix of sprite urls in WebVTT track, sprite file names are used by default
+    pub fn url_prefix(mut self, prefix: &str) -> Self {
+        self.url_prefix = prefix.to_owned();
+        self
+    }
+
+    /// save sprites as `sprite_001.jpg`, `sprite_002.jpg`... into `dir`,
+    /// along with `thumbnails.vtt`
+    pub fn save(&self, dir: &str) -> Result<SpriteSheetOutput, Error> {
+        if self.interval.is_zero() || self.tile_cols == 0 || self.tile_rows == 0 {
+            return Err(Error {
+                kind: ErrorKind::InvalidOption,
+                msg: "interval and tile size of sprite sheet must not be zero".to_owned(),
+            });
+        }
+        std::fs::create_dir_all(dir)?;
+        let dir = Path::new(dir);
+        let pattern = dir.join("sprite_%03d.jpg");
+        let interval = self.interval.as_secs_f64();
+
+        let result = FFMpeg::input(&self.file)
+            .output()
+            .video_filter(&format!("fps=1/{interval}"))
+            .video_filter(&format!("scale={}:-2", self.thumb_width))
+            .video_filter(&format!("tile={}x{}", self.tile_cols, self.tile_rows))
+            .args(vec!["-an", "-sn", "-q:v", "3"])
+            .verbose()
+            .run(&pattern.to_string_lossy())?;
+
+        let duration = parse_duration(&result.stderr).ok_or(Error {
+            kind: ErrorKind::UnexpectedOutput,
+            msg: "failed to get duration of input".to_owned(),
+        })?;
+        let (_, sprite_height) = parse_output_video_size(&result.stderr).ok_or(Error {
+            kind: ErrorKind::UnexpectedOutput,
+            msg: "failed to get size of sprite".to_owned(),
+        })?;
+        let thumb_height = sprite_height / self.tile_rows;
+
+        let per_sprite = (self.tile_cols * self.tile_rows) as usize;
+        let count = (duration.as_secs_f64() / interval).ceil() as usize;
+        let mut vtt = String::from("WEBVTT\n");
+        let mut sprites = vec![];
+        for i in 0..count {
+            let name = format!("sprite_{:03}.jpg", i / per_sprite + 1);
+            if i % per_sprite == 0 {
+                sprites.push(dir.join(&name).to_string_lossy().into_owned());
+            }
+            let tile = (i % per_sprite) as u32;
+            let x = (tile % self.tile_cols) * self.thumb_width;
+            let y = (tile / self.tile_cols) * thumb_height;
+            let start = self.interval * i as u32;
+            let end = std::cmp::min(self.interval * (i as u32 + 1), duration);
+            vtt.push_str(&format!(
+                "\n{} --> {}\n{}{name}#xywh={x},{y},{},{thumb_height}\n",
+                format_vtt_time(&start),
+                format_vtt_time(&end),
+                self.url_prefix,
+                self.thumb_width,
+            ));
+        }
+
+        let vtt_path = dir.join("thumbnails.vtt");
+        std::fs::write(&vtt_path, vtt)?;
+        Ok(SpriteSheetOutput {
+            sprites,
+            vtt: vtt_path.to_string_lossy().into_owned(),
+        })
+    }
+}
+
+/// which frames `tools::screenshots` takes
+#[derive(Clone, Debug)]
+pub enum ScreenshotSpec {
+    /// frames at given timestamps
+    Timestamps(Vec<time::Duration>),
+    /// one frame every given duration, starting from the first frame
+    Every(time::Duration),
+    /// given number of frames, evenly spaced over the whole video
+    Count(u32),
+    /// every keyframe, without decoding other frames
+    Keyframes,
+}
+
+pub struct Screenshots {
+    file: String,
+    spec: ScreenshotSpec,
+    size: Option<(i32, i32)>,
+    best_of: Option<u32>,
+}
+
+/// take multiple screenshots of input video in a single ffmpeg run
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::tools::{self, ScreenshotSpec};
+/// # use ffmpeg_cli_utils::FFMpeg;
+/// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+/// let files = tools::screenshots("./sample.mp4", ScreenshotSpec::Count(10))
+///     .resize(-2, 320)
+///     .save("./output/thumb_%03d.jpg")
+///     .unwrap();
+/// let images = tools::screenshots("./sample.mp4", ScreenshotSpec::Keyframes)
+///     .to_images()
+///     .unwrap();
+/// ```
+pub fn screenshots(file: &str, spec: ScreenshotSpec) -> Screenshots {
+    Screenshots {
+        file: file.to_owned(),
+        spec,
+        size: None,
+        best_of: None,
+    }
+}
+
+impl Screenshots {
+    pub fn resize(mut self, width: i32, height: i32) -> Self {
+        self.size = Some((width, height));
+        self
+    }
+
+    /// replace each batch of `frames` selected frames with the most
+    /// representative one, by using the `thumbnail` filter
+    pub fn best_of(mut self, frames: u32) -> Self {
+        self.best_of = Some(frames);
+        self
+    }
+
+    /// save screenshots by an image2 file name pattern, e.g. `thumb_%03d.jpg`,
+    /// returns paths of saved files
+    pub fn save(&self, pattern: &str) -> Result<Vec<String>, Error> {
+        let mut output = self.output()?.args(vec!["-progress", "pipe:2", "-nostats"]);
+        let result = output.run(pattern)?;
+        // image2 numbers files from 1, one per frame written
+        let frames = parse_progress_frames(&result.stderr).ok_or(Error {
+            kind: ErrorKind::UnexpectedOutput,
+            msg: "failed to get number of screenshots".to_owned(),
+        })?;
+        let mut files: Vec<String> = (1..=frames)
+            .map(|n| expand_sequence_pattern(pattern, n))
+            .collect();
+        // a pattern without sequence number is overwritten by every frame
+        files.dedup();
+        Ok(files)
+    }
+
+    /// take screenshots into memory as jpeg images
+    pub fn to_images(&self) -> Result<Vec<Vec<u8>>, Error> {
+        let mut output = self
+            .output()?
+            .format("image2pipe")
+            .args(vec!["-c:v", "mjpeg"]);
+        let result = output.run_raw("pipe:1")?;
+        Ok(split_jpeg_images(&result.stdout))
+    }
+
+    fn output(&self) -> Result<crate::output::FFmpegOutput, Error> {
+        let mut input = FFMpeg::input(&self.file);
+        let mut filters = vec![];
+        match self.spec {
+            ScreenshotSpec::Timestamps(ref timestamps) => {
+                let (input_start, filter) = select_timestamps(timestamps);
+                input = input.start_time(&input_start);
+                filters.push(filter);
+            }
+            ScreenshotSpec::Count(count) => {
+                let duration = analysis::duration(&self.file)?;
+                let timestamps: Vec<_> = (0..count)
+                    .map(|i| duration.mul_f64((i as f64 + 0.5) / count as f64))
+                    .collect();
+                let (input_start, filter) = select_timestamps(&timestamps);
+                input = input.start_time(&input_start);
+                filters.push(filter);
+            }
+            ScreenshotSpec::Every(interval) => {
+                if interval.is_zero() {
+                    return Err(Error {
+                        kind: ErrorKind::InvalidOption,
+                        msg: "screenshot interval must be greater than zero".to_owned(),
+                    });
+                }
+                filters.push(format!("fps=1/{}", interval.as_secs_f64()));
+            }
+            ScreenshotSpec::Keyframes => {
+                input = input.args(vec!["-skip_frame", "nokey"]);
+            }
+        }
+        if let Some(frames) = self.best_of {
+            filters.push(format!("thumbnail={frames}"));
+        }
+
+        let mut output = input.output();
+        if let Some((width, height)) = self.size {
+            output = output.resize(width, height);
+        }
+        for filter in &filters {
+            output = output.video_filter(filter);
+        }
+        Ok(output.args(vec!["-an", "-sn", "-vsync", "vfr"]))
+    }
+}
+
+/// `select` filter passing the first frame at or after each timestamp, input is
+/// seeked to the whole second before the earliest timestamp to skip decoding
+fn select_timestamps(timestamps: &[time::Duration]) -> (time::Duration, String) {
+    let input_start = timestamps
+        .iter()
+        .min()
+        .map(|t| time::Duration::from_secs(t.as_secs()))
+        .unwrap_or_default();
+    let expr = timestamps
+        .iter()
+        .map(|t| {
+            let t = (*t - input_start).as_secs_f64();
+            format!("gte(t,{t})*(isnan(prev_pts)+lt(prev_pts*TB,{t}))")
+        })
+        .collect::<Vec<_>>()
+        .join("+");
+    let expr = if expr.is_empty() { "0".to_owned() } else { expr };
+    (input_start, format!("select={}", escape_filter_value(&expr)))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AnimatedFormat {
+    Gif,
+    WebP,
+    Apng,
+}
+
+/// dithering of `paletteuse` for GIF output
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Dither {
+    /// ordered 8x8 bayer dithering, with scale from 0 to 5
+    Bayer(u8),
+    FloydSteinberg,
+    Sierra2_4a,
+    None,
+}
+
+impl Dither {
+    fn to_option(self) -> String {
+        match self {
+            Dither::Bayer(scale) => format!("dither=bayer:bayer_scale={}", scale.min(5)),
+            Dither::FloydSteinberg => "dither=floyd_steinberg".to_owned(),
+            Dither::Sierra2_4a => "dither=sierra2_4a".to_owned(),
+            Dither::None => "dither=none".to_owned(),
+        }
+    }
+}
+
+pub struct Animated {
+    file: String,
+    start: time::Duration,
+    duration: time::Duration,
+    format: AnimatedFormat,
+    fps: u32,
+    width: Option<u32>,
+    plays: u32,
+    max_colors: u32,
+    dither: Dither,
+    quality: u8,
+    lossless: bool,
+}
+
+/// short looping animation of input video, from `start` lasting `duration`
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::tools::{self, AnimatedFormat};
+/// use std::time;
+/// # use ffmpeg_cli_utils::FFMpeg;
+/// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+/// tools::animated(
+///     "./sample.mp4",
+///     &time::Duration::from_secs(30),
+///     &time::Duration::from_secs(3),
+///     AnimatedFormat::Gif,
+/// )
+/// .fps(12)
+/// .width(320)
+/// .save("./output/preview.gif")
+/// .unwrap();
+/// ```
+pub fn animated(
+    file: &str,
+    start: &time::Duration,
+    duration: &time::Duration,
+    format: AnimatedFormat,
+) -> Animated {
+    Animated {
+        file: file.to_owned(),
+        start: *start,
+        duration: *duration,
+        format,
+        fps: 10,
+        width: None,
+        plays: 0,
+        max_colors: 256,
+        dither: Dither::Sierra2_4a,
+        quality: 75,
+        lossless: false,
+    }
+}
+
+impl Animated {
+    /// frame rate of animation, 10 by default
+    pub fn fps(mut self, fps: u32) -> Self {
+        self.fps = fps;
+        self
+    }
+
+    /// scale animation to `width`, keeping aspect ratio
+    pub fn width(mut self, width: u32) -> Self {
+        self.width = Some(width);
+        self
+    }
+
+    /// number of times animation is played, 0 (default) means infinitely
+    pub fn plays(mut self, plays: u32) -> Self {
+        self.plays = plays;
+        self
+    }
+
+    /// max colors of generated GIF palette, from 2 to 256
+    pub fn max_colors(mut self, max_colors: u32) -> Self {
+        self.max_colors = max_colors.clamp(2, 256);
+        self
+    }
+
+    /// dithering used when mapping GIF frames to the palette
+    pub fn dither(mut self, dither: Dither) -> Self {
+        self.dither = dither;
+        self
+    }
+
+    /// quality of lossy WebP, from 0 to 100, 75 by default
+    pub fn quality(mut self, quality: u8) -> Self {
+        self.quality = quality.min(100);
+        self
+    }
+
+    /// encode WebP losslessly
+    pub fn lossless(mut self) -> Self {
+        self.lossless = true;
+        self
+    }
+
+    pub fn output(&self) -> crate::output::FFmpegOutput {
+        let mut filters = vec![format!("fps={}", self.fps)];
+        if let Some(width) = self.width {
+            filters.push(format!("scale={width}:-1:flags=lanczos"));
+        }
+        let mut output = FFMpeg::input(&self.file)
+            .start_time(&self.start)
+            .output()
+            .args(vec!["-t".to_owned(), self.duration.as_secs_f64().to_string()])
+            .args(vec!["-an", "-sn"]);
+
+        match self.format {
+            AnimatedFormat::Gif => {
+                // palette is generated from the same frames in one graph, then applied to them
+                filters.push(format!(
+                    "split[frames][palette_src];[palette_src]palettegen=max_colors={}:stats_mode=diff[palette];[frames][palette]paletteuse={}",
+                    self.max_colors,
+                    self.dither.to_option(),
+                ));
+                // gif counts repeats after the first play, -1 disables looping
+                let loop_count = match self.plays {
+                    0 => 0,
+                    1 => -1,
+                    plays => plays as i64 - 1,
+                };
+                output = output
+                    .args(vec!["-loop".to_owned(), loop_count.to_string()])
+                    .format("gif");
+            }
+            AnimatedFormat::WebP => {
+                output = output.args(vec!["-c:v", "libwebp"]);
+                output = if self.lossless {
+                    output.args(vec!["-lossless", "1"])
+                } else {
+                    output.args(vec!["-quality".to_owned(), self.quality.to_string()])
+                };
+                output = output
+                    .args(vec!["-loop".to_owned(), self.plays.to_string()])
+                    .format("webp");
+            }
+            AnimatedFormat::Apng => {
+                output = output
+                    .args(vec!["-c:v", "apng"])
+                    .args(vec!["-plays".to_owned(), self.plays.to_string()])
+                    .format("apng");
+            }
+        }
+        for filter in &filters {
+            output = output.video_filter(filter);
+        }
+        output
+    }
+
+    pub fn save(&self, file: &str) -> Result<crate::output::SpawnResult, Error> {
+        self.output().save(file)
+    }
+}
+
+/// position of an overlay on the video
+#[derive(Clone, Debug, PartialEq)]
+pub enum Position {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+    /// x and y expressions of `overlay` filter, e.g. `W-w-10` and `(H-h)/2`
+    Custom(String, String),
+}
+
+impl Position {
+    /// overlay x and y expressions, `margin` is not applied to `Custom` positions
+    pub(crate) fn to_expr(&self, margin: u32) -> (String, String) {
+        match self {
+            Position::TopLeft => (format!("{margin}"), format!("{margin}")),
+            Position::TopRight => (format!("W-w-{margin}"), format!("{margin}")),
+            Position::BottomLeft => (format!("{margin}"), format!("H-h-{margin}")),
+            Position::BottomRight => (format!("W-w-{margin}"), format!("H-h-{margin}")),
+            Position::Center => ("(W-w)/2".to_owned(), "(H-h)/2".to_owned()),
+            Position::Custom(x, y) => (x.to_owned(), y.to_owned()),
+        }
+    }
+}
+
+pub struct Watermark {
+    video: String,
+    image: String,
+    position: Position,
+    margin: u32,
+    opacity: f32,
+    scale: Option<f32>,
+    window: Option<(time::Duration, time::Duration)>,
+    loop_logo: bool,
+}
+
+/// overlay `image` on `video`, `opacity` ranges from 0 to 1, when
+/// `scale_relative_to_video` is given the logo is scaled to that fraction of
+/// video width, keeping its aspect ratio
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::tools::{self, Position};
+/// use std::time;
+/// # use ffmpeg_cli_utils::FFMpeg;
+/// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+/// tools::watermark("./sample.mp4", "./logo.png", Position::BottomRight, 20, 0.8, Some(0.15))
+///     .between(&time::Duration::from_secs(5), &time::Duration::from_secs(20))
+///     .save("./output/watermarked.mp4")
+///     .unwrap();
+/// ```
+pub fn watermark(
+    video: &str,
+    image: &str,
+    position: Position,
+    margin: u32,
+    opacity: f32,
+    scale_relative_to_video: Option<f32>,
+) -> Watermark {
+    Watermark {
+        video: video.to_owned(),
+        image: image.to_owned(),
+        position,
+        margin,
+        opacity: opacity.clamp(0.0, 1.0),
+        scale: scale_relative_to_video,
+        window: None,
+        loop_logo: false,
+    }
+}
+
+impl Watermark {
+    /// only show logo from `start` to `end` of the video
+    pub fn between(mut self, start: &time::Duration, end: &time::Duration) -> Self {
+        self.window = Some((*start, *end));
+        self
+    }
+
+    /// loop an animated logo (GIF or video) for the whole video, by default
+    /// its last frame stays after it ends
+    pub fn loop_logo(mut self) -> Self {
+        self.loop_logo = true;
+        self
+    }
+
+    /// output built on `filter_complex`, simple filters such as `resize` can
+    /// not be added to it
+    pub fn output(&self) -> crate::output::FFmpegOutput {
+        let video = FFMpegInput::input(&self.video);
+        let mut logo = FFMpegInput::input(&self.image);
+        if self.loop_logo {
+            logo = if self.image.to_lowercase().ends_with(".gif") {
+                logo.args(vec!["-ignore_loop", "0"])
+            } else {
+                logo.args(vec!["-stream_loop", "-1"])
+            };
+        }
+
+        let mut graph = vec![format!(
+            "[1:v]format=rgba,colorchannelmixer=aa={}[logo]",
+            self.opacity
+        )];
+        let main = if let Some(scale) = self.scale {
+            graph.push(format!(
+                "[logo][0:v]scale2ref=w=main_w*{scale}:h=ow/a[logo][main]"
+            ));
+            "[main]"
+        } else {
+            "[0:v]"
+        };
+        let (x, y) = self.position.to_expr(self.margin);
+        let mut overlay = format!(
+            "overlay=x={}:y={}",
+            escape_filter_value(&x),
+            escape_filter_value(&y)
+        );
+        if self.loop_logo {
+            // looped logo never ends, stop with the video instead
+            overlay.push_str(":shortest=1");
+        }
+        if let Some((start, end)) = self.window {
+            let enable = format!("between(t,{},{})", start.as_secs_f64(), end.as_secs_f64());
+            overlay.push_str(&format!(":enable={}", escape_filter_value(&enable)));
+        }
+        graph.push(format!("{main}[logo]{overlay}[video]"));
+
+        FFMpegMultipleInput::merge(&video, &logo)
+            .output()
+            .filter_complex(&graph.join(";"))
+            .args(vec!["-map", "[video]", "-map", "0:a?", "-c:a", "copy"])
+    }
+
+    pub fn save(&self, file: &str) -> Result<crate::output::SpawnResult, Error> {
+        self.output().save(file)
+    }
+}
+
+/// change playback speed of input by `factor`, e.g. 2.0 for 2x and 0.5 for
+/// slow motion, audio tempo follows without changing pitch
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::tools;
+/// # use ffmpeg_cli_utils::FFMpeg;
+/// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+/// tools::speed("./sample.mp4", 2.0)
+///     .unwrap()
+///     .save("./output/2x.mp4")
+///     .unwrap();
+/// ```
+pub fn speed(file: &str, factor: f64) -> Result<crate::output::FFmpegOutput, Error> {
+    if !factor.is_finite() || factor <= 0.0 {
+        return Err(Error {
+            kind: ErrorKind::InvalidOption,
+            msg: format!("speed factor must be a positive number, got {factor}"),
+        });
+    }
+    Ok(FFMpeg::input(file)
+        .output()
+        .video_filter(&format!("setpts=PTS/{factor}"))
+        .audio_filter(&atempo_chain(factor)))
+}
+
+/// `atempo` only accepts factors from 0.5 to 2.0, larger changes are chained
+fn atempo_chain(factor: f64) -> String {
+    let mut factor = factor;
+    let mut filters = vec![];
+    while factor > 2.0 {
+        filters.push("atempo=2".to_owned());
+        factor /= 2.0;
+    }
+    while factor < 0.5 {
+        filters.push("atempo=0.5".to_owned());
+        factor /= 0.5;
+    }
+    filters.push(format!("atempo={factor}"));
+    filters.join(",")
+}
+
+/// play input `n` times in a row, without re-reading it from disk for each
+/// loop, e.g. `n = 3` for three plays
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::tools;
+/// # use ffmpeg_cli_utils::FFMpeg;
+/// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+/// tools::loop_n("./output/preview.mp4", 3)
+///     .save("./output/looped.mp4")
+///     .unwrap();
+/// ```
+pub fn loop_n(file: &str, n: u32) -> crate::output::FFmpegOutput {
+    let loops = n.saturating_sub(1).to_string();
+    FFMpeg::input(file)
+        .args(vec!["-stream_loop", &loops])
+        .output()
+}
+
+pub struct Reverse {
+    file: String,
+    chunk_duration: time::Duration,
+}
+
+/// play input backwards, both video and audio
+///
+/// `reverse` filters keep the whole clip in memory, so long inputs are
+/// reversed in chunks of `chunk_duration` (30 seconds by default) which are
+/// joined in reverse order
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::tools;
+/// # use ffmpeg_cli_utils::FFMpeg;
+/// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+/// tools::reverse("./sample.mp4")
+///     .save("./output/reversed.mp4")
+///     .unwrap();
+/// ```
+pub fn reverse(file: &str) -> Reverse {
+    Reverse {
+        file: file.to_owned(),
+        chunk_duration: time::Duration::from_secs(30),
+    }
+}
+
+impl Reverse {
+    pub fn chunk_duration(mut self, chunk_duration: &time::Duration) -> Self {
+        self.chunk_duration = *chunk_duration;
+        self
+    }
+
+    pub fn save(&self, file: &str) -> Result<(), Error> {
+        if self.chunk_duration.is_zero() {
+            return Err(Error {
+                kind: ErrorKind::InvalidOption,
+                msg: "chunk duration of reverse must not be zero".to_owned(),
+            });
+        }
+        let duration = analysis::duration(&self.file)?;
+        if duration <= self.chunk_duration {
+            reverse_output(FFMpeg::input(&self.file)).save(file)?;
+            return Ok(());
+        }
+
+        let temp_dir = tempfile::tempdir()?;
+        let mut chunks = vec![];
+        let mut start = time::Duration::ZERO;
+        while start < duration {
+            let chunk = temp_dir
+                .path()
+                .join(format!("chunk_{:05}.mkv", chunks.len()))
+                .to_string_lossy()
+                .into_owned();
+            let input = FFMpeg::input(&self.file)
+                .args(vec!["-ss".to_owned(), start.as_secs_f64().to_string()]);
+            // chunks are kept nearly lossless until they are joined
+            reverse_output(input)
+                .args(vec![
+                    "-t".to_owned(),
+                    self.chunk_duration.as_secs_f64().to_string(),
+                ])
+                .args(vec![
+                    "-c:v",
+                    "libx264",
+                    "-preset",
+                    "ultrafast",
+                    "-crf",
+                    "12",
+                    "-c:a",
+                    "flac",
+                ])
+                .save(&chunk)?;
+            chunks.push(chunk);
+            start += self.chunk_duration;
+        }
+
+        let chunks: Vec<&str> = chunks.iter().rev().map(|chunk| chunk.as_str()).collect();
+        FFMpegMultipleInput::concat(&chunks).output().save(file)?;
+        Ok(())
+    }
+}
+
+fn reverse_output(input: FFMpegInput) -> crate::output::FFmpegOutput {
+    input
+        .output()
+        .video_filter("reverse")
+        .audio_filter("areverse")
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StackDirection {
+    Horizontal,
+    Vertical,
+}
+
+/// how `tools::compose` arranges its inputs
+#[derive(Clone, Debug, PartialEq)]
+pub enum Layout {
+    /// second input is scaled to `scale` of the first input width and placed at `corner` over it,
+    /// takes exactly 2 inputs
+    PiP {
+        corner: Position,
+        scale: f32,
+    },
+    SideBySide,
+    Stack(StackDirection),
+    /// inputs fill a grid row by row, with `cols` cells per row
+    Grid {
+        cols: u32,
+    },
+}
+
+/// when composed output ends, for inputs of different durations
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DurationMode {
+    /// end with the shortest input
+    Shortest,
+    /// end with the longest input, shorter inputs hold their last frame
+    Longest,
+}
+
+pub struct Compose {
+    inputs: Vec<String>,
+    layout: Layout,
+    cell_size: Option<(u32, u32)>,
+    duration: DurationMode,
+    audio_from: usize,
+    margin: u32,
+}
+
+/// compose multiple videos into one frame, e.g. picture-in-picture or grid
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::tools::{self, DurationMode, Layout, Position};
+/// # use ffmpeg_cli_utils::FFMpeg;
+/// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+/// tools::compose(&["./sample.mp4", "./sample1.mp4"], Layout::SideBySide)
+///     .cell_size(640, 360)
+///     .duration(DurationMode::Longest)
+///     .save("./output/side_by_side.mp4")
+///     .unwrap();
+/// tools::compose(
+///     &["./sample.mp4", "./sample1.mp4"],
+///     Layout::PiP { corner: Position::BottomRight, scale: 0.25 },
+/// )
+/// .save("./output/pip.mp4")
+/// .unwrap();
+/// ```
+pub fn compose(inputs: &[&str], layout: Layout) -> Compose {
+    Compose {
+        inputs: inputs.iter().map(|input| input.to_string()).collect(),
+        layout,
+        cell_size: None,
+        duration: DurationMode::Shortest,
+        audio_from: 0,
+        margin: 16,
+    }
+}
+
+impl Compose {
+    /// size every input is scaled and padded to, size of the first input by default,
+    /// ignored by picture-in-picture
+    pub fn cell_size(mut self, width: u32, height: u32) -> Self {
+        self.cell_size = Some((width, height));
+        self
+    }
+
+    pub fn duration(mut self, duration: DurationMode) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    /// index of input whose audio is kept, the first input by default
+    pub fn audio_from(mut self, input: usize) -> Self {
+        self.audio_from = input;
+        self
+    }
+
+    /// distance of picture-in-picture from the corner of the frame
+    pub fn margin(mut self, margin: u32) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    /// picture-in-picture takes exactly 2 inputs. the output is built on
+    /// `filter_complex`, so `resize`, `crop` and video or audio filters can not
+    /// be added to it
+    pub fn output(&self) -> Result<crate::output::FFmpegOutput, Error> {
+        // hstack, vstack and xstack reject a single input as well
+        if self.inputs.len() < 2 || self.audio_from >= self.inputs.len() {
+            return Err(Error {
+                kind: ErrorKind::InvalidOption,
+                msg: format!(
+                    "{} inputs are not enough for layout or audio source",
+                    self.inputs.len()
+                ),
+            });
+        }
+        // the overlay only places the second input, a third one would be left unconnected
+        if matches!(self.layout, Layout::PiP { .. }) && self.inputs.len() != 2 {
+            return Err(Error {
+                kind: ErrorKind::InvalidOption,
+                msg: format!(
+                    "picture-in-picture takes exactly 2 inputs, got {}",
+                    self.inputs.len()
+                ),
+            });
+        }
+
+        let infos = self
+            .inputs
+            .iter()
+            .map(|input| analysis::probe(input))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let (width, height) = match self.cell_size {
+            Some(size) => size,
+            None => infos[0]
+                .streams
+                .iter()
+                .find_map(|stream| Some((stream.width?, stream.height?)))
+                .ok_or(Error {
+                    kind: ErrorKind::StreamSelection,
+                    msg: format!("no video stream found in {}", self.inputs[0]),
+                })?,
+        };
+        let longest = infos
+            .iter()
+            .filter_map(|info| info.duration)
+            .max()
+            .unwrap_or_default();
+
+        let mut graph = vec![];
+        let n = self.inputs.len();
+        for (i, info) in infos.iter().enumerate() {
+            let mut filters = vec![];
+            if !matches!(self.layout, Layout::PiP { .. }) {
+                filters.push(format!(
+                    "scale={width}:{height}:force_original_aspect_ratio=decrease,pad={width}:{height}:(ow-iw)/2:(oh-ih)/2,setsar=1"
+                ));
+            }
+            if let (DurationMode::Longest, Some(duration)) = (self.duration, info.duration) {
+                let pad = longest.saturating_sub(duration).as_secs_f64();
+                if pad > 0.0 {
+                    filters.push(format!("tpad=stop_mode=clone:stop_duration={pad}"));
+                }
+            }
+            if filters.is_empty() {
+                filters.push("null".to_owned());
+            }
+            graph.push(format!("[{i}:v]{}[v{i}]", filters.join(",")));
+        }
+
+        let shortest = match self.duration {
+            DurationMode::Shortest => 1,
+            DurationMode::Longest => 0,
+        };
+        let labels: String = (0..n).map(|i| format!("[v{i}]")).collect();
+        match self.layout {
+            Layout::PiP { ref corner, scale } => {
+                let (x, y) = corner.to_expr(self.margin);
+                graph.push(format!(
+                    "[v1][v0]scale2ref=w=main_w*{scale}:h=ow/a[pip][main]"
+                ));
+                graph.push(format!(
+                    "[main][pip]overlay=x={}:y={}:shortest={shortest}:eof_action=pass[video]",
+                    escape_filter_value(&x),
+                    escape_filter_value(&y)
+                ));
+            }
+            Layout::SideBySide | Layout::Stack(StackDirection::Horizontal) => {
+                graph.push(format!(
+                    "{labels}hstack=inputs={n}:shortest={shortest}[video]"
+                ));
+            }
+            Layout::Stack(StackDirection::Vertical) => {
+                graph.push(format!(
+                    "{labels}vstack=inputs={n}:shortest={shortest}[video]"
+                ));
+            }
+            Layout::Grid { cols } => {
+                let cols = cols.max(1) as usize;
+                let cells: Vec<String> = (0..n)
+                    .map(|i| {
+                        format!(
+                            "{}_{}",
+                            (i % cols) as u32 * width,
+                            (i / cols) as u32 * height
+                        )
+                    })
+                    .collect();
+                graph.push(format!(
+                    "{labels}xstack=inputs={n}:layout={}:fill=black:shortest={shortest}[video]",
+                    cells.join("|")
+                ));
+            }
+        }
+
+        let mut inputs = FFMpegMultipleInput::new(&FFMpegInput::input(&self.inputs[0]));
+        let rest: Vec<FFMpegInput> = self.inputs[1..].iter().map(FFMpegInput::input).collect();
+        inputs.append(rest.iter().collect());
+        Ok(inputs
+            .output()
+            .filter_complex(&graph.join(";"))
+            .args(vec!["-map".to_owned(), "[video]".to_owned()])
+            .args(vec!["-map".to_owned(), format!("{}:a?", self.audio_from)]))
+    }
+
+    pub fn save(&self, file: &str) -> Result<crate::output::SpawnResult, Error> {
+        self.output()?.save(file)
+    }
+}
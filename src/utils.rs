@@ -7,14 +7,178 @@ pub fn format_time(t: &time::Duration) -> String {
     format!("{}:{}:{}", hours, minutes, seconds)
 }
 
-pub fn read_to_string<T: std::io::Read>(r: &mut T) -> String {
-    let mut s_buf = String::with_capacity(512);
-    let mut s = String::new();
-    while let Ok(size) = r.read_to_string(&mut s_buf) {
-        if size == 0 {
-            break;
+/// collect `"key" : "value"` pairs of the last flat json object printed in `s`,
+/// which is how filters like `loudnorm` report their results on stderr
+pub fn parse_json_fields(s: &str) -> Vec<(String, String)> {
+    let start = match s.rfind('{') {
+        Some(start) => start,
+        None => return vec![],
+    };
+    let end = s[start..].find('}').map(|end| start + end).unwrap_or(s.len());
+    s[start + 1..end]
+        .split(',')
+        .filter_map(|field| {
+            let (key, value) = field.split_once(':')?;
+            let key = key.trim().trim_matches('"');
+            let value = value.trim().trim_matches('"');
+            Some((key.to_owned(), value.to_owned()))
+        })
+        .collect()
+}
+
+/// escape `value` to be used as an option value of a filter inside a filtergraph,
+/// both filter option level and graph level escaping are applied
+pub fn escape_filter_value(value: &str) -> String {
+    let mut option = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | '\'' | ':') {
+            option.push('\\');
+        }
+        option.push(c);
+    }
+    let mut graph = String::with_capacity(option.len());
+    for c in option.chars() {
+        if matches!(c, '\\' | '\'' | '[' | ']' | ',' | ';') {
+            graph.push('\\');
+        }
+        graph.push(c);
+    }
+    graph
+}
+
+/// format as WebVTT timestamp, e.g. 01:02:03.040
+pub fn format_vtt_time(t: &time::Duration) -> String {
+    let millis = t.subsec_millis();
+    let seconds = t.as_secs() % 60;
+    let minutes = (t.as_secs() / 60) % 60;
+    let hours = (t.as_secs() / 60) / 60;
+    format!("{hours:02}:{minutes:02}:{seconds:02}.{millis:03}")
+}
+
+/// parse duration of first input from ffmpeg log, e.g. "  Duration: 00:00:52.21, start: ..."
+pub fn parse_duration(stderr: &str) -> Option<time::Duration> {
+    let (_, rest) = stderr.split_once("Duration: ")?;
+    parse_time(rest.split(',').next()?.trim())
+}
+
+/// parse ffmpeg time duration, `[[HH:]MM:]SS[.m...]`, as written by `format_time`
+pub fn parse_time(value: &str) -> Option<time::Duration> {
+    let mut seconds = 0.0;
+    for part in value.split(':') {
+        seconds = seconds * 60.0 + part.parse::<f64>().ok()?;
+    }
+    Some(time::Duration::from_secs_f64(seconds))
+}
+
+/// parse size of first video stream of first output from ffmpeg log, e.g.
+/// "  Stream #0:0: Video: mjpeg, yuvj420p(pc, progressive), 1600x900, ..."
+pub fn parse_output_video_size(stderr: &str) -> Option<(u32, u32)> {
+    let (_, output) = stderr.split_once("Output #0")?;
+    let line = output.lines().find(|line| line.contains("Video:"))?;
+    line.split(|c: char| c == ',' || c.is_whitespace())
+        .find_map(|column| {
+            let (w, h) = column.split_once('x')?;
+            Some((w.parse::<u32>().ok()?, h.parse::<u32>().ok()?))
+        })
+}
+
+/// parse frame rate printed by ffmpeg, e.g. "23.98" or "1k", as an exact
+/// fraction. ffmpeg rounds rates to 2 decimals, NTSC rates of N*1000/1001
+/// are recovered from that rounding
+pub fn parse_frame_rate(value: &str) -> Option<(u32, u32)> {
+    let rate = match value.strip_suffix('k') {
+        Some(thousands) => thousands.parse::<f64>().ok()? * 1000.0,
+        None => value.parse::<f64>().ok()?,
+    };
+    if !rate.is_finite() || rate <= 0.0 {
+        return None;
+    }
+    let hundredths = (rate * 100.0).round() as u32;
+    if hundredths.is_multiple_of(100) {
+        return Some((hundredths / 100, 1));
+    }
+    let ntsc = (rate * 1.001).round();
+    if (ntsc * 1000.0 / 1001.0 - rate).abs() < 0.006 {
+        return Some((ntsc as u32 * 1000, 1001));
+    }
+    let divisor = gcd(hundredths, 100);
+    Some((hundredths / divisor, 100 / divisor))
+}
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// parse number of frames written from the last `-progress` report, e.g.
+/// "frame=12"
+pub fn parse_progress_frames(stderr: &str) -> Option<usize> {
+    stderr
+        .lines()
+        .rev()
+        .find_map(|line| line.trim().strip_prefix("frame=")?.trim().parse().ok())
+}
+
+/// expand printf style sequence number of image2 file name pattern, e.g.
+/// `thumb_%03d.jpg` => `thumb_007.jpg`
+pub fn expand_sequence_pattern(pattern: &str, n: usize) -> String {
+    let start = match pattern.find('%') {
+        Some(start) => start,
+        None => return pattern.to_owned(),
+    };
+    let rest = &pattern[start + 1..];
+    let end = match rest.find('d') {
+        Some(end) if rest[..end].chars().all(|c| c.is_ascii_digit()) => end,
+        _ => return pattern.to_owned(),
+    };
+    let width = rest[..end].parse::<usize>().unwrap_or(0);
+    format!("{}{n:0width$}{}", &pattern[..start], &rest[end + 1..])
+}
+
+/// split concatenated jpeg images, as produced by `-f image2pipe -c:v mjpeg`
+pub fn split_jpeg_images(data: &[u8]) -> Vec<Vec<u8>> {
+    let mut images = vec![];
+    let mut start = None;
+    let mut i = 0;
+    while i + 1 < data.len() {
+        match (data[i], data[i + 1]) {
+            (0xff, 0xd8) if start.is_none() => start = Some(i),
+            (0xff, 0xd9) => {
+                if let Some(s) = start.take() {
+                    images.push(data[s..i + 2].to_vec());
+                }
+            }
+            _ => (),
+        }
+        i += 1;
+    }
+    images
+}
+
+/// match `path` against a glob where `*` and `?` stay within a path segment
+/// and `**` matches any number of segments
+pub fn glob_match(glob: &str, path: &str) -> bool {
+    fn matches(glob: &[u8], path: &[u8]) -> bool {
+        match glob {
+            [] => path.is_empty(),
+            [b'*', b'*', b'/', rest @ ..] => {
+                matches(rest, path)
+                    || path
+                        .iter()
+                        .enumerate()
+                        .any(|(i, c)| *c == b'/' && matches(rest, &path[i + 1..]))
+            }
+            [b'*', b'*', rest @ ..] => (0..=path.len()).any(|i| matches(rest, &path[i..])),
+            [b'*', rest @ ..] => {
+                let segment = path.iter().position(|c| *c == b'/').unwrap_or(path.len());
+                (0..=segment).any(|i| matches(rest, &path[i..]))
+            }
+            [b'?', rest @ ..] => !path.is_empty() && path[0] != b'/' && matches(rest, &path[1..]),
+            [c, rest @ ..] => path.first() == Some(c) && matches(rest, &path[1..]),
         }
-        s.push_str(&s_buf[0..size]);
     }
-    s
+    matches(glob.as_bytes(), path.as_bytes())
 }
@@ -0,0 +1,304 @@
+use std::{fmt, time};
+
+use crate::{
+    analysis::{self, MediaInfo},
+    error::{Error, ErrorKind},
+    input::StreamType,
+    FFMpeg,
+};
+
+/// checks of `FFmpegOutput::verify`, on top of the properties implied by
+/// options of the output, i.e. duration of inputs and trim window, size of
+/// `resize` and codecs given as `-c:v`/`-c:a`
+///
+/// samples:
+/// ```
+/// use ffmpeg_cli_utils::{verify::Verification, FFMpeg};
+/// # FFMpeg::set_ffmpeg_bin("./ffmpeg");
+/// let mut output = FFMpeg::input("./sample.mp4")
+///     .output()
+///     .resize(-2, 320)
+///     .args(vec!["-c:v", "libx264"]);
+/// output.save("./output/verified.mp4").unwrap();
+/// let report = output
+///     .verify(
+///         "./output/verified.mp4",
+///         &Verification::new().stream_count(2).full_decode(),
+///     )
+///     .unwrap();
+/// assert!(report.passed(), "{report}");
+/// ```
+#[derive(Clone, Debug)]
+pub struct Verification {
+    duration: Option<time::Duration>,
+    check_duration: bool,
+    duration_tolerance: time::Duration,
+    stream_count: Option<usize>,
+    video_codec: Option<String>,
+    audio_codec: Option<String>,
+    full_decode: bool,
+}
+
+impl Default for Verification {
+    fn default() -> Self {
+        Self {
+            duration: None,
+            check_duration: true,
+            duration_tolerance: time::Duration::from_secs(1),
+            stream_count: None,
+            video_codec: None,
+            audio_codec: None,
+            full_decode: false,
+        }
+    }
+}
+
+impl Verification {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// expected duration, instead of the one worked out from inputs, e.g.
+    /// when filters change speed
+    pub fn duration(mut self, duration: time::Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    pub fn skip_duration(mut self) -> Self {
+        self.check_duration = false;
+        self
+    }
+
+    /// max difference between expected and actual duration, default is 1s
+    pub fn duration_tolerance(mut self, tolerance: time::Duration) -> Self {
+        self.duration_tolerance = tolerance;
+        self
+    }
+
+    pub fn stream_count(mut self, count: usize) -> Self {
+        self.stream_count = Some(count);
+        self
+    }
+
+    /// codec name as reported by ffmpeg, e.g. `h264`, not encoder `libx264`
+    pub fn video_codec(mut self, codec: &str) -> Self {
+        self.video_codec = Some(codec.to_owned());
+        self
+    }
+
+    /// codec name as reported by ffmpeg, e.g. `aac`
+    pub fn audio_codec(mut self, codec: &str) -> Self {
+        self.audio_codec = Some(codec.to_owned());
+        self
+    }
+
+    /// decode whole output to catch corruption that probing can not see
+    pub fn full_decode(mut self) -> Self {
+        self.full_decode = true;
+        self
+    }
+}
+
+/// single property compared by `FFmpegOutput::verify`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Check {
+    /// e.g. `duration` or `video codec`
+    pub name: &'static str,
+    pub expected: String,
+    pub actual: String,
+    pub passed: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct VerificationReport {
+    /// probe result of output
+    pub info: MediaInfo,
+    pub checks: Vec<Check>,
+    /// errors reported while decoding whole output, `None` if not decoded
+    pub decode_errors: Option<String>,
+}
+
+impl VerificationReport {
+    pub fn passed(&self) -> bool {
+        self.failures().next().is_none()
+            && self
+                .decode_errors
+                .as_ref()
+                .is_none_or(|errors| errors.is_empty())
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &Check> {
+        self.checks.iter().filter(|check| !check.passed)
+    }
+}
+
+impl fmt::Display for VerificationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            let result = if check.passed { "ok" } else { "FAILED" };
+            writeln!(
+                f,
+                "{}: expected {}, got {} ... {result}",
+                check.name, check.expected, check.actual
+            )?;
+        }
+        match self.decode_errors {
+            Some(ref errors) if !errors.is_empty() => {
+                writeln!(f, "full decode ... FAILED\n{}", errors.trim_end())
+            }
+            Some(_) => writeln!(f, "full decode ... ok"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// properties of output implied by options of `FFmpegOutput`
+#[derive(Debug, Default)]
+pub(crate) struct Expected {
+    pub duration: Option<time::Duration>,
+    /// size given to `resize`, 0 keeps the input dimension and negative ones
+    /// are worked out by ffmpeg
+    pub size: Option<(i32, i32)>,
+    pub video_encoder: Option<String>,
+    pub audio_encoder: Option<String>,
+}
+
+pub(crate) fn verify(
+    file: &str,
+    expected: Expected,
+    verification: &Verification,
+) -> Result<VerificationReport, Error> {
+    let info = analysis::probe(file)?;
+    let mut checks = vec![];
+    let video = info
+        .streams
+        .iter()
+        .find(|stream| stream.stream_type == StreamType::Video);
+    let audio = info
+        .streams
+        .iter()
+        .find(|stream| stream.stream_type == StreamType::Audio);
+
+    let expected_duration = verification.duration.or(expected.duration);
+    if let (true, Some(expected_duration)) = (verification.check_duration, expected_duration) {
+        let passed = info.duration.is_some_and(|duration| {
+            duration.abs_diff(expected_duration) <= verification.duration_tolerance
+        });
+        checks.push(Check {
+            name: "duration",
+            expected: format!(
+                "{:.2}s ± {:.2}s",
+                expected_duration.as_secs_f64(),
+                verification.duration_tolerance.as_secs_f64()
+            ),
+            actual: info
+                .duration
+                .map_or("none".to_owned(), |d| format!("{:.2}s", d.as_secs_f64())),
+            passed,
+        });
+    }
+
+    if let Some(count) = verification.stream_count {
+        checks.push(Check {
+            name: "stream count",
+            expected: count.to_string(),
+            actual: info.streams.len().to_string(),
+            passed: info.streams.len() == count,
+        });
+    }
+
+    if let Some((width, height)) = expected.size {
+        let actual = video.and_then(|video| Some((video.width?, video.height?)));
+        let dimension_matches = |expected: i32, actual: u32| match expected {
+            // 0 keeps the input dimension
+            0 => true,
+            // -n keeps aspect ratio and rounds to a multiple of n
+            n if n < 0 => actual.is_multiple_of(n.unsigned_abs()),
+            n => actual == n as u32,
+        };
+        checks.push(Check {
+            name: "resolution",
+            expected: format!("{width}x{height}"),
+            actual: actual.map_or("no video".to_owned(), |(w, h)| format!("{w}x{h}")),
+            passed: actual
+                .is_some_and(|(w, h)| dimension_matches(width, w) && dimension_matches(height, h)),
+        });
+    }
+
+    let video_codec = verification
+        .video_codec
+        .clone()
+        .or_else(|| expected.video_encoder.as_deref().and_then(codec_of_encoder));
+    let audio_codec = verification
+        .audio_codec
+        .clone()
+        .or_else(|| expected.audio_encoder.as_deref().and_then(codec_of_encoder));
+    for (name, codec, stream) in [
+        ("video codec", video_codec, video),
+        ("audio codec", audio_codec, audio),
+    ] {
+        if let Some(codec) = codec {
+            let actual = stream.map(|stream| stream.codec.clone());
+            checks.push(Check {
+                name,
+                passed: actual.as_deref() == Some(codec.as_str()),
+                expected: codec,
+                actual: actual.unwrap_or_else(|| "no stream".to_owned()),
+            });
+        }
+    }
+
+    let decode_errors = if verification.full_decode {
+        Some(decode(file)?)
+    } else {
+        None
+    };
+    Ok(VerificationReport {
+        info,
+        checks,
+        decode_errors,
+    })
+}
+
+/// decode `file` into the null muxer and return errors ffmpeg reported
+fn decode(file: &str) -> Result<String, Error> {
+    let output = std::process::Command::new(FFMpeg::get_ffmpeg_bin())
+        .args(["-hide_banner", "-v", "error", "-i", file, "-f", "null", "-"])
+        .stdin(std::process::Stdio::null())
+        .output()?;
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    if !output.status.success() && stderr.is_empty() {
+        return Err(Error {
+            kind: ErrorKind::Ffmpeg,
+            msg: format!("decoding {file} failed with {}", output.status),
+        });
+    }
+    Ok(stderr)
+}
+
+/// codec name ffmpeg reports for streams written by `encoder`, `None` when
+/// streams are copied
+fn codec_of_encoder(encoder: &str) -> Option<String> {
+    let codec = match encoder {
+        "copy" => return None,
+        "libx264" | "libopenh264" => "h264",
+        "libx265" => "hevc",
+        "libvpx" => "vp8",
+        "libvpx-vp9" => "vp9",
+        "libaom-av1" | "libsvtav1" | "librav1e" => "av1",
+        "libmp3lame" => "mp3",
+        "libopus" => "opus",
+        "libvorbis" => "vorbis",
+        "libfdk_aac" => "aac",
+        // hardware encoders are named after codec, e.g. h264_nvenc
+        encoder => match encoder.rsplit_once('_') {
+            Some((
+                codec,
+                "nvenc" | "qsv" | "vaapi" | "videotoolbox" | "amf" | "v4l2m2m" | "mf",
+            )) => codec,
+            _ => encoder,
+        },
+    };
+    Some(codec.to_owned())
+}